@@ -1,26 +1,75 @@
 extern crate env_logger;
 extern crate wasm_gc;
 extern crate getopts;
+extern crate rustc_demangle;
+extern crate serde_json;
 
 use std::env;
+use std::error::Error;
 use std::fs::File;
-use std::io::{Read, Write};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::process;
 
 use getopts::Options;
 
 fn main() {
     env_logger::init().unwrap();
 
+    if let Err(e) = run() {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
     let mut opts = Options::new();
     opts.optopt("o", "", "set output file name", "NAME");
     opts.optflag("", "no-demangle", "don't demangle symbol names");
+    opts.optopt("", "blacklist-preset",
+                "start from a built-in blacklist of dropped exports \
+                 (compiler-rt, none)", "PRESET");
+    opts.optmulti("", "blacklist", "drop exports named NAME", "NAME");
+    opts.optmulti("", "unblacklist",
+                  "keep exports named NAME even if blacklisted", "NAME");
+    opts.optmulti("", "remove-export",
+                  "remove exports matching the glob PATTERN", "PATTERN");
+    opts.optmulti("", "export-allowlist",
+                  "remove all exports not matching one of these glob \
+                   PATTERNs", "PATTERN");
+    opts.optflag("", "reject-unlisted-exports",
+                 "fail instead of removing exports not on the allowlist");
+    opts.optmulti("", "keep",
+                  "keep exports, imported functions and named functions \
+                   matching the glob PATTERN", "PATTERN");
+    opts.optmulti("", "keep-file",
+                  "read --keep patterns from FILE, one per line", "FILE");
+    opts.optmulti("", "keep-function",
+                  "keep the function at INDEX", "INDEX");
+    opts.optopt("", "report",
+                "print what was kept and removed in FORMAT (text, json)",
+                "FORMAT");
+    opts.optopt("", "remap-file",
+                "write the mapping from old to new indices to FILE as JSON",
+                "FILE");
+    opts.optopt("", "source-map",
+                "rewrite the source map IN of the input to OUT for the output",
+                "IN:OUT");
+    opts.optflag("", "reject-undeclared-features",
+                 "fail if the module uses a feature missing from its \
+                  target_features section");
+    opts.optflag("", "no-processed-by",
+                 "don't record wasm-gc in the producers section");
+    opts.optflag("", "dry-run",
+                 "print what would be removed instead of writing output");
+    opts.optmulti("", "why",
+                  "print the shortest chain of references keeping the \
+                   item named NAME, such as a function, global or export, \
+                   (or the function at INDEX) alive instead of writing \
+                   output", "NAME|INDEX");
     opts.optflag("h", "help", "print this help menu");
-	let args: Vec<_> = env::args().collect();
-	let program = args[0].clone();
-    let matches = match opts.parse(&args[1..]) {
-        Ok(m) => { m }
-        Err(f) => { panic!(f.to_string()) }
-    };
+    let args: Vec<_> = env::args().collect();
+    let program = args[0].clone();
+    let matches = opts.parse(&args[1..])?;
     if matches.opt_present("h") {
         return print_usage(&program, opts)
     }
@@ -36,17 +85,198 @@ fn main() {
         2 => (matches.free[0].clone(), matches.free[1].clone()),
         _ => return print_usage(&program, opts),
     };
+    let report = matches.opt_str("report");
+    match report.as_ref().map(|s| &s[..]) {
+        None | Some("text") | Some("json") => {}
+        Some(s) => return Err(format!("unknown report format: {}", s).into()),
+    }
 
     let mut contents = Vec::new();
-    File::open(&input).unwrap().read_to_end(&mut contents).unwrap();
+    File::open(&input)
+        .and_then(|mut f| f.read_to_end(&mut contents))
+        .map_err(|e| format!("failed to read `{}`: {}", input, e))?;
 
     let mut cfg = wasm_gc::Config::new();
     cfg.demangle(!matches.opt_present("no-demangle"));
-    let result = cfg.gc(&contents).expect("failed to parse wasm module");
-    File::create(&output).unwrap().write_all(&result).unwrap();
+    cfg.record_processed_by(!matches.opt_present("no-processed-by"));
+    match matches.opt_str("blacklist-preset").as_ref().map(|s| &s[..]) {
+        None | Some("compiler-rt") => {
+            cfg.set_blacklist(wasm_gc::COMPILER_RT_BLACKLIST.iter().cloned());
+        }
+        Some("none") => { cfg.clear_blacklist(); }
+        Some(s) => return Err(format!("unknown blacklist preset: {}", s).into()),
+    }
+    for name in matches.opt_strs("blacklist") {
+        cfg.blacklist(&name);
+    }
+    for name in matches.opt_strs("unblacklist") {
+        cfg.unblacklist(&name);
+    }
+    for pattern in matches.opt_strs("remove-export") {
+        cfg.remove_export(&pattern);
+    }
+    let allowlist = matches.opt_strs("export-allowlist");
+    if !allowlist.is_empty() {
+        cfg.export_allowlist(allowlist);
+    }
+    cfg.reject_unlisted_exports(matches.opt_present("reject-unlisted-exports"));
+    cfg.reject_undeclared_features(matches.opt_present("reject-undeclared-features"));
+    for pattern in matches.opt_strs("keep") {
+        cfg.keep(&pattern);
+    }
+    for file in matches.opt_strs("keep-file") {
+        let read_err = |e| format!("failed to read `{}`: {}", file, e);
+        let f = File::open(&file).map_err(read_err)?;
+        for line in BufReader::new(f).lines() {
+            let line = line.map_err(read_err)?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue
+            }
+            cfg.keep(line);
+        }
+    }
+    for index in matches.opt_strs("keep-function") {
+        let index = index.parse()
+            .map_err(|_| format!("invalid function index: {}", index))?;
+        cfg.keep_function_index(index);
+    }
+    let source_map = match matches.opt_str("source-map") {
+        Some(s) => {
+            let (map_input, map_output) = split_source_map(&s)
+                .ok_or_else(|| format!("invalid source map, expected IN:OUT: {}", s))?;
+            let mut map = String::new();
+            File::open(map_input)
+                .and_then(|mut f| f.read_to_string(&mut map))
+                .map_err(|e| format!("failed to read `{}`: {}", map_input, e))?;
+            Some((map, map_output.to_string()))
+        }
+        None => None,
+    };
+    let gc_err = |e| format!("failed to gc `{}`: {}", input, e);
+    let why = matches.opt_strs("why");
+    if !why.is_empty() {
+        let analysis = cfg.analyze(&contents).map_err(gc_err)?;
+        for name in why {
+            print_why(&analysis, &name, !matches.opt_present("no-demangle"))?;
+        }
+        return Ok(())
+    }
+    if matches.opt_present("dry-run") {
+        let analysis = cfg.analyze(&contents).map_err(gc_err)?;
+        let format = report.unwrap_or_else(|| "text".to_string());
+        return print_report(analysis.report(), &format)
+    }
+    let remap_file = matches.opt_str("remap-file");
+    let result = if report.is_some() || remap_file.is_some() || source_map.is_some() {
+        let (result, analysis, map) = match source_map {
+            Some((ref map, _)) => {
+                let (result, analysis, map) = cfg.source_map(&contents, map)
+                    .map_err(gc_err)?;
+                (result, analysis, Some(map))
+            }
+            None => {
+                let (result, analysis) = cfg.gc_with_analysis(&contents).map_err(gc_err)?;
+                (result, analysis, None)
+            }
+        };
+        if let Some(format) = report {
+            print_report(analysis.report(), &format)?;
+        }
+        if let Some(file) = remap_file {
+            let json = serde_json::to_string_pretty(analysis.remapping())?;
+            File::create(&file)
+                .and_then(|mut f| writeln!(f, "{}", json))
+                .map_err(|e| format!("failed to write `{}`: {}", file, e))?;
+        }
+        if let (Some((_, file)), Some(map)) = (source_map, map) {
+            File::create(&file)
+                .and_then(|mut f| writeln!(f, "{}", map))
+                .map_err(|e| format!("failed to write `{}`: {}", file, e))?;
+        }
+        result
+    } else {
+        cfg.gc(&contents).map_err(gc_err)?
+    };
+    File::create(&output)
+        .and_then(|mut f| f.write_all(&result))
+        .map_err(|e| format!("failed to write `{}`: {}", output, e))?;
+    Ok(())
+}
+
+/// Splits the `IN:OUT` argument of `--source-map`.
+///
+/// Both paths may start with a Windows drive such as `C:\`, so the
+/// separator is the first colon that isn't part of a drive of IN.
+fn split_source_map(s: &str) -> Option<(&str, &str)> {
+    let b = s.as_bytes();
+    let drive = b.len() >= 3 && b[0].is_ascii_alphabetic() && b[1] == b':' &&
+        (b[2] == b'\\' || b[2] == b'/');
+    let start = if drive { 2 } else { 0 };
+    let i = start + s[start..].find(':')?;
+    Some((&s[..i], &s[i + 1..]))
+}
+
+fn print_why(analysis: &wasm_gc::Analysis, name: &str, demangle: bool)
+    -> Result<(), Box<dyn Error>>
+{
+    let item = match name.parse() {
+        Ok(index) => wasm_gc::Item::Function(index),
+        Err(_) => {
+            analysis.find(name).ok_or_else(|| format!("no item named `{}`", name))?
+        }
+    };
+    let describe = |item| {
+        match analysis.name(item) {
+            Some(name) if demangle => {
+                format!("{} `{}`", item, rustc_demangle::demangle(name))
+            }
+            Some(name) => format!("{} `{}`", item, name),
+            None => item.to_string(),
+        }
+    };
+    match analysis.why_live(item) {
+        Some(path) => {
+            println!("{} is live because of:", describe(item));
+            for item in path {
+                println!("    {}", describe(item));
+            }
+        }
+        None => println!("{} is not live", describe(item)),
+    }
+    Ok(())
+}
+
+fn print_report(report: &wasm_gc::GcReport, format: &str) -> Result<(), Box<dyn Error>> {
+    if format == "json" {
+        println!("{}", serde_json::to_string_pretty(report)?);
+        return Ok(())
+    }
+    let verb = if report.output_size.is_some() { "removed" } else { "would remove" };
+    for &(kind, items) in report.categories().iter() {
+        for item in items.iter().filter(|item| !item.kept) {
+            match item.name {
+                Some(ref name) => {
+                    println!("{} {} {} `{}` ({} bytes)",
+                             verb, kind, item.index, name, item.size);
+                }
+                None => {
+                    println!("{} {} {} ({} bytes)", verb, kind, item.index, item.size);
+                }
+            }
+        }
+    }
+    match report.output_size {
+        Some(size) => println!("{} bytes -> {} bytes", report.input_size, size),
+        None => {
+            println!("{} bytes, about {} bytes would be saved",
+                     report.input_size, report.removed_size());
+        }
+    }
+    Ok(())
 }
 
-fn print_usage(program: &str, opts: Options) {
+fn print_usage(program: &str, opts: Options) -> Result<(), Box<dyn Error>> {
     let brief = format!("Usage: {} [options] <INPUT> [OUTPUT]", program);
     print!("{}", opts.usage(&brief));
     println!("
@@ -67,7 +297,51 @@ Usage of this command typically looks like:
     # Passing various optiosn
     wasm-gc --no-demangle input.wasm -o output.wasm
 
+    # Keep a `main` export that would otherwise be dropped
+    wasm-gc --unblacklist main input.wasm output.wasm
+
+    # Keep everything named `ext_*` even if nothing references it
+    wasm-gc --keep 'ext_*' input.wasm output.wasm
+
+    # Remove exports the host doesn't need, along with their code
+    wasm-gc --remove-export 'debug_*' input.wasm output.wasm
+
+    # Fail if the module exports anything besides `main` and `memory`
+    wasm-gc --export-allowlist main --export-allowlist memory \
+        --reject-unlisted-exports input.wasm output.wasm
+
+    # Print everything that was removed, as JSON
+    wasm-gc --report json input.wasm output.wasm
+
+    # Save how indices were renumbered, e.g. to symbolize stack traces
+    wasm-gc --remap-file remap.json input.wasm output.wasm
+
+    # Keep the source map of the module in sync with its code
+    wasm-gc --source-map input.wasm.map:output.wasm.map input.wasm output.wasm
+
+    # Check what would be removed without writing any output
+    wasm-gc --dry-run input.wasm
+
+    # Explain why a function wasn't removed
+    wasm-gc --why my_function input.wasm
+
 Please reports bugs to https://github.com/alexcrichton/wasm-gc if you find
 them!
 ");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::split_source_map;
+
+    #[test]
+    fn source_map_paths() {
+        assert_eq!(split_source_map("in.map:out.map"), Some(("in.map", "out.map")));
+        assert_eq!(split_source_map(r"C:\a.map:D:\b.map"), Some((r"C:\a.map", r"D:\b.map")));
+        assert_eq!(split_source_map("C:/a.map:b.map"), Some(("C:/a.map", "b.map")));
+        assert_eq!(split_source_map(r"a.map:D:\b.map"), Some(("a.map", r"D:\b.map")));
+        assert_eq!(split_source_map("in.map"), None);
+        assert_eq!(split_source_map(r"C:\in.map"), None);
+    }
 }
@@ -0,0 +1,100 @@
+extern crate serde_json;
+extern crate wasm_gc;
+extern crate wat;
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+use serde_json::Value;
+use wasm_gc::Config;
+
+/// A scratch directory for the files of one test, removed afterwards.
+struct Scratch(PathBuf);
+
+impl Scratch {
+    fn new(test: &str) -> Scratch {
+        let dir = env::temp_dir().join(format!("wasm-gc-cli-{}-{}", test, std::process::id()));
+        drop(fs::remove_dir_all(&dir));
+        fs::create_dir_all(&dir).unwrap();
+        Scratch(dir)
+    }
+
+    fn write(&self, name: &str, contents: &[u8]) -> String {
+        let path = self.0.join(name);
+        fs::write(&path, contents).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    fn path(&self, name: &str) -> String {
+        self.0.join(name).to_str().unwrap().to_string()
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        drop(fs::remove_dir_all(&self.0));
+    }
+}
+
+fn wasm_gc(args: &[&str]) -> Output {
+    let output = Command::new(env!("CARGO_BIN_EXE_wasm-gc"))
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    output
+}
+
+const MODULE: &str = r#"
+    (module
+        (func $api (export "api")
+            call $helper)
+        (func $helper)
+        (func $unused)
+        (func $other))
+"#;
+
+#[test]
+fn keep_file() {
+    let dir = Scratch::new("keep-file");
+    let module = wat::parse_str(MODULE).unwrap();
+    let input = dir.write("in.wasm", &module);
+    let keep = dir.write("keep.txt", b"# kept for the host\n\n  unused  \n");
+    let output = dir.path("out.wasm");
+    wasm_gc(&["--keep-file", &keep, &input, &output]);
+    let expected = Config::new().keep("unused").gc(&module).unwrap();
+    assert_eq!(fs::read(&output).unwrap(), expected);
+}
+
+/// Returns the names of the functions the JSON report `stdout` lists as
+/// kept.
+fn kept_functions(stdout: &[u8]) -> Vec<String> {
+    let report: Value = serde_json::from_slice(stdout).unwrap();
+    report["functions"].as_array()
+        .unwrap()
+        .iter()
+        .filter(|f| f["kept"].as_bool().unwrap())
+        .map(|f| f["name"].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[test]
+fn report_json() {
+    let dir = Scratch::new("report-json");
+    let input = dir.write("in.wasm", &wat::parse_str(MODULE).unwrap());
+    let output = dir.path("out.wasm");
+    let stdout = wasm_gc(&["--report", "json", &input, &output]).stdout;
+    let report: Value = serde_json::from_slice(&stdout).unwrap();
+    assert_eq!(report["input_size"], fs::metadata(&input).unwrap().len());
+    assert_eq!(report["output_size"], fs::metadata(&output).unwrap().len());
+    assert_eq!(report["functions"][2], serde_json::json!({
+        "index": 2,
+        "name": "unused",
+        "size": 3,
+        "kept": false,
+    }));
+    assert_eq!(report["exports"][0]["name"], "api");
+    assert_eq!(kept_functions(&stdout), ["api", "helper"]);
+}
@@ -0,0 +1,201 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::fmt;
+
+use rustc_demangle;
+use report::GcReport;
+
+/// An item of a wasm module that the gc pass tracks.
+///
+/// Functions, tables, memories, globals and tags are identified by their index
+/// space, so imported items come first. Exports and segments are identified
+/// by their position in their section.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Item {
+    Function(u32),
+    Table(u32),
+    Memory(u32),
+    Global(u32),
+    Tag(u32),
+    Type(u32),
+    Export(u32),
+    DataSegment(u32),
+    ElementSegment(u32),
+    /// The start function entry of the module.
+    Start,
+    /// A root requested through `Config`, e.g. with `keep_function_index`.
+    Keep,
+}
+
+impl fmt::Display for Item {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Item::Function(i) => write!(f, "function {}", i),
+            Item::Table(i) => write!(f, "table {}", i),
+            Item::Memory(i) => write!(f, "memory {}", i),
+            Item::Global(i) => write!(f, "global {}", i),
+            Item::Tag(i) => write!(f, "tag {}", i),
+            Item::Type(i) => write!(f, "type {}", i),
+            Item::Export(i) => write!(f, "export {}", i),
+            Item::DataSegment(i) => write!(f, "data segment {}", i),
+            Item::ElementSegment(i) => write!(f, "element segment {}", i),
+            Item::Start => write!(f, "start"),
+            Item::Keep => write!(f, "keep"),
+        }
+    }
+}
+
+/// The result of the gc pass's liveness analysis of a module.
+#[derive(Default)]
+pub struct Analysis {
+    pub(crate) codes: BTreeSet<u32>,
+    pub(crate) tables: BTreeSet<u32>,
+    pub(crate) memories: BTreeSet<u32>,
+    pub(crate) globals: BTreeSet<u32>,
+    pub(crate) tags: BTreeSet<u32>,
+    pub(crate) types: BTreeSet<u32>,
+    pub(crate) imports: BTreeSet<u32>,
+    pub(crate) exports: BTreeSet<u32>,
+    pub(crate) data_segments: BTreeSet<u32>,
+    pub(crate) element_segments: BTreeSet<u32>,
+    pub(crate) ref_functions: BTreeSet<u32>,
+    pub(crate) roots: BTreeSet<Item>,
+    pub(crate) referrers: BTreeMap<Item, BTreeSet<Item>>,
+    pub(crate) names: BTreeMap<Item, String>,
+    pub(crate) report: GcReport,
+    pub(crate) remapping: Remapping,
+}
+
+impl Analysis {
+    /// Returns whether `item` is kept by the gc pass.
+    pub fn is_live(&self, item: Item) -> bool {
+        self.roots.contains(&item) || self.referrers.contains_key(&item)
+    }
+
+    /// Returns a report of everything the gc pass would keep and remove.
+    ///
+    /// The report's `output_size` is only filled in if the module was
+    /// rewritten, e.g. by `Config::gc_with_analysis`. For an analysis of
+    /// `Config::analyze` it's `None` and `GcReport::removed_size` estimates
+    /// the savings instead.
+    pub fn report(&self) -> &GcReport {
+        &self.report
+    }
+
+    /// Returns how the gc pass renumbers the items it keeps.
+    pub fn remapping(&self) -> &Remapping {
+        &self.remapping
+    }
+
+    /// Returns the shortest chain of references from a root to `item`, or
+    /// `None` if `item` isn't live.
+    ///
+    /// The first element of the path is the root, such as an export or
+    /// `Item::Start`, and the last is `item` itself.
+    pub fn why_live(&self, item: Item) -> Option<Vec<Item>> {
+        let mut queue = VecDeque::new();
+        let mut seen = HashSet::new();
+        let mut retains = HashMap::new();
+        queue.push_back(item);
+        seen.insert(item);
+        while let Some(cur) = queue.pop_front() {
+            if self.roots.contains(&cur) {
+                let mut path = vec![cur];
+                let mut at = cur;
+                while let Some(&next) = retains.get(&at) {
+                    path.push(next);
+                    at = next;
+                }
+                return Some(path)
+            }
+            if let Some(referrers) = self.referrers.get(&cur) {
+                for &referrer in referrers {
+                    if seen.insert(referrer) {
+                        retains.insert(referrer, cur);
+                        queue.push_back(referrer);
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// Returns the name of `item`, if it has one.
+    ///
+    /// Items are named by the `name` section, imported functions without a
+    /// name there by `module.field`, and exports by their field. Names are
+    /// returned as they appear in the module, without demangling.
+    pub fn name(&self, item: Item) -> Option<&str> {
+        self.names.get(&item).map(|s| &s[..])
+    }
+
+    /// Looks up an item by its name, matching either the mangled or the
+    /// demangled form (with or without the trailing hash).
+    ///
+    /// Any named item can be found, such as a global or a data segment. If
+    /// several items share the name, the first in the order of `Item` is
+    /// returned, so functions take precedence over the others.
+    pub fn find(&self, name: &str) -> Option<Item> {
+        self.names.iter()
+            .find(|&(_, n)| {
+                let demangled = rustc_demangle::demangle(n);
+                n == name ||
+                    demangled.to_string() == name ||
+                    format!("{:#}", demangled) == name
+            })
+            .map(|(&item, _)| item)
+    }
+}
+
+/// How the gc pass renumbers the index spaces of a module.
+///
+/// Each table is indexed by an item's index in the input module and holds
+/// its index in the output module, or `None` if the item was removed.
+/// Imported items come first, as in the index spaces themselves, and
+/// segments are indexed by their position in their section.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Remapping {
+    pub functions: Vec<Option<u32>>,
+    pub globals: Vec<Option<u32>>,
+    pub types: Vec<Option<u32>>,
+    pub tables: Vec<Option<u32>>,
+    pub memories: Vec<Option<u32>>,
+    pub tags: Vec<Option<u32>>,
+    pub data_segments: Vec<Option<u32>>,
+    pub element_segments: Vec<Option<u32>>,
+}
+
+impl Remapping {
+    /// Returns the index of `item` in the output module, or `None` if it was
+    /// removed or isn't part of a remapped index space.
+    pub fn new_index(&self, item: Item) -> Option<u32> {
+        let (map, index) = self.map(item)?;
+        map.get(index as usize).and_then(|&i| i)
+    }
+
+    /// Returns the index in the input module of the item that ends up as
+    /// `item` in the output module.
+    ///
+    /// This is the reverse of `new_index` and is what's needed to translate
+    /// e.g. a stack trace of the gc'd module back to the original.
+    pub fn old_index(&self, item: Item) -> Option<u32> {
+        let (map, index) = self.map(item)?;
+        map.iter()
+            .position(|&i| i == Some(index))
+            .map(|i| i as u32)
+    }
+
+    fn map(&self, item: Item) -> Option<(&[Option<u32>], u32)> {
+        match item {
+            Item::Function(i) => Some((&self.functions, i)),
+            Item::Global(i) => Some((&self.globals, i)),
+            Item::Type(i) => Some((&self.types, i)),
+            Item::Table(i) => Some((&self.tables, i)),
+            Item::Memory(i) => Some((&self.memories, i)),
+            Item::Tag(i) => Some((&self.tags, i)),
+            Item::DataSegment(i) => Some((&self.data_segments, i)),
+            Item::ElementSegment(i) => Some((&self.element_segments, i)),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,90 @@
+/// The built-in "compiler-rt" blacklist preset.
+///
+/// These are symbols which rustc and LLVM export from every wasm module but
+/// which are almost never intended to be part of the module's public
+/// interface, such as compiler-rt intrinsics and `main`. Exports with these
+/// names are dropped (and are therefore not roots for the gc pass) unless the
+/// blacklist is reconfigured through `Config`.
+pub static COMPILER_RT_BLACKLIST: &[&str] = &[
+    "main",
+    "__ashldi3",
+    "__ashlti3",
+    "__ashrdi3",
+    "__ashrti3",
+    "__lshrdi3",
+    "__lshrti3",
+    "__floatsisf",
+    "__floatsidf",
+    "__floatdidf",
+    "__floattisf",
+    "__floattidf",
+    "__floatunsisf",
+    "__floatunsidf",
+    "__floatundidf",
+    "__floatuntisf",
+    "__floatuntidf",
+    "__fixsfsi",
+    "__fixsfdi",
+    "__fixsfti",
+    "__fixdfsi",
+    "__fixdfdi",
+    "__fixdfti",
+    "__fixunssfsi",
+    "__fixunssfdi",
+    "__fixunssfti",
+    "__fixunsdfsi",
+    "__fixunsdfdi",
+    "__fixunsdfti",
+    "__udivsi3",
+    "__umodsi3",
+    "__udivmodsi4",
+    "__udivdi3",
+    "__udivmoddi4",
+    "__umoddi3",
+    "__udivti3",
+    "__udivmodti4",
+    "__umodti3",
+    "memcpy",
+    "memmove",
+    "memset",
+    "memcmp",
+    "__powisf2",
+    "__powidf2",
+    "__addsf3",
+    "__adddf3",
+    "__subsf3",
+    "__subdf3",
+    "__divsi3",
+    "__divdi3",
+    "__divti3",
+    "__divdf3",
+    "__divsf3",
+    "__modsi3",
+    "__moddi3",
+    "__modti3",
+    "__divmodsi4",
+    "__divmoddi4",
+    "__muldi3",
+    "__multi3",
+    "__muldf3",
+    "__mulsf3",
+    "__mulosi4",
+    "__mulodi4",
+    "__muloti4",
+    "rust_eh_personality",
+];
+
+/// Exports that the thread runtime of a module with a shared memory relies
+/// on.
+///
+/// A host spawning a thread calls `__wasm_init_tls` with a block of
+/// `__tls_size` bytes aligned to `__tls_align`, after which `__tls_base`
+/// points at it. Modules with a shared memory keep these exports even if
+/// they aren't on the export allowlist, unless they're removed explicitly
+/// through `Config::blacklist` or `Config::remove_export`.
+pub static THREAD_RUNTIME_EXPORTS: &[&str] = &[
+    "__wasm_init_tls",
+    "__tls_base",
+    "__tls_size",
+    "__tls_align",
+];
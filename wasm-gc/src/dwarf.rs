@@ -0,0 +1,457 @@
+use std::ops::Range;
+
+use gimli::{self, EndianSlice, LittleEndian, Reader, SectionId};
+use gimli::read;
+use gimli::write::{self, Address, AttributeValue, ConvertError, ConvertLineProgram};
+use gimli::write::{ConvertLineSequenceEnd, ConvertResult, ConvertUnit, ConvertUnitEntry};
+use gimli::write::{FileId, FilterUnitSection, LineProgram, Location, LocationList, RangeList};
+use module::Module;
+
+/// Where the code of each function that the gc pass kept ended up.
+///
+/// Addresses are offsets into the contents of the code section, which is
+/// what DWARF uses as code addresses in wasm.
+#[derive(Default)]
+pub struct CodeMap {
+    functions: Vec<FunctionMap>,
+}
+
+struct FunctionMap {
+    old: Range<u64>,
+    new: Range<u64>,
+    /// The old and new offset of each instruction from the start of the
+    /// function body, in order.
+    instructions: Vec<(u64, u64)>,
+}
+
+impl CodeMap {
+    /// Records that the function body at `old` in the input is now at `new`.
+    ///
+    /// Functions have to be added in the order of the code section.
+    pub fn add_function(&mut self,
+                        old: Range<usize>,
+                        new: Range<usize>,
+                        instructions: Vec<(usize, usize)>) {
+        self.functions.push(FunctionMap {
+            old: old.start as u64..old.end as u64,
+            new: new.start as u64..new.end as u64,
+            instructions: instructions.into_iter()
+                .map(|(old, new)| (old as u64, new as u64))
+                .collect(),
+        });
+    }
+
+    /// Returns the new address of the code at `address` of the input, or
+    /// `None` if it was removed.
+    ///
+    /// The end of a function body maps to the end of the new body, so this
+    /// also works for the exclusive end of a range.
+    pub fn map(&self, address: u64) -> Option<u64> {
+        let i = self.functions.partition_point(|f| f.old.start <= address);
+        let f = &self.functions[i.checked_sub(1)?];
+        if address > f.old.end {
+            return None
+        }
+        if address == f.old.end {
+            return Some(f.new.end)
+        }
+        let offset = address - f.old.start;
+        let new = match f.instructions.partition_point(|&(old, _)| old <= offset) {
+            0 => 0,
+            i => f.instructions[i - 1].1,
+        };
+        Some(f.new.start + new)
+    }
+
+    /// Returns the new address range of `range`, or `None` if the code it
+    /// starts in was removed or nothing is left of it.
+    fn map_range(&self, range: &read::Range) -> Option<Range<u64>> {
+        let begin = self.map(range.begin)?;
+        let end = match self.map(range.end) {
+            Some(end) => end,
+            None => self.function_end(range.begin)?,
+        };
+        if begin < end {
+            Some(begin..end)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the new end of the function that `address` of the input is
+    /// part of.
+    fn function_end(&self, address: u64) -> Option<u64> {
+        let i = self.functions.partition_point(|f| f.old.start <= address);
+        let f = &self.functions[i.checked_sub(1)?];
+        if address <= f.old.end {
+            Some(f.new.end)
+        } else {
+            None
+        }
+    }
+}
+
+/// Sections holding DWARF that refers to nothing else that is rewritten, and
+/// is kept as it is.
+const KEPT_SECTIONS: &[&str] = &[".debug_macinfo"];
+
+/// Returns whether `name` is the name of a custom section holding DWARF.
+pub fn is_debug_section(name: &str) -> bool {
+    name.starts_with(".debug_")
+}
+
+/// Rewrites the DWARF of `module` for the code layout described by `code`.
+///
+/// Debug info entries and line table rows of removed functions are dropped,
+/// along with compile units that only described removed code, and every
+/// code address is translated to where the code ended up. Returns the new
+/// `.debug_*` custom sections; sections that can't be rewritten, such as
+/// `.debug_aranges` and `.debug_pubnames`, are left out since consumers
+/// can rebuild them from `.debug_info`.
+///
+/// Fails with a description of the problem if the DWARF is malformed.
+pub fn rewrite(module: &Module, code: &CodeMap) -> Result<Vec<(String, Vec<u8>)>, String> {
+    let load = |id: SectionId| -> Result<_, gimli::Error> {
+        let data = module.custom_section(id.name()).map(|s| s.data()).unwrap_or(&[]);
+        Ok(EndianSlice::new(data, LittleEndian))
+    };
+    let dwarf = read::Dwarf::load(load).map_err(|e| e.to_string())?;
+    let mut out = convert(&dwarf, code).map_err(|e| e.to_string())?;
+
+    let mut sections = write::Sections::new(write::EndianVec::new(LittleEndian));
+    out.write(&mut sections).map_err(|e| e.to_string())?;
+    let mut rewritten = Vec::new();
+    sections.for_each(|id, data| {
+        if !data.slice().is_empty() {
+            rewritten.push((id.name().to_string(), data.slice().to_vec()));
+        }
+        Ok::<(), gimli::Error>(())
+    }).map_err(|e| e.to_string())?;
+
+    for s in module.customs.iter().filter(|s| is_debug_section(s.name())) {
+        if KEPT_SECTIONS.contains(&s.name()) {
+            rewritten.push((s.name().to_string(), s.data().to_vec()));
+        } else if !rewritten.iter().any(|(name, _)| name == s.name()) {
+            info!("removing custom section: {}", s.name());
+        }
+    }
+    Ok(rewritten)
+}
+
+fn convert<R: Reader<Offset = usize>>(dwarf: &read::Dwarf<R>, code: &CodeMap)
+    -> ConvertResult<write::Dwarf>
+{
+    // Everything at the top level of a unit that isn't code, such as global
+    // variables and types, is kept along with the code that's still there.
+    // The filter then pulls in whatever those entries refer to.
+    let mut filter = FilterUnitSection::new(dwarf)?;
+    while let Some(mut unit) = filter.read_unit()? {
+        let dead = is_dead_unit(unit.read_unit, code)?;
+        let mut entry = unit.null_entry();
+        while unit.read_entry(&mut entry)? {
+            let needed = match is_live(entry.read_unit, &entry, code)? {
+                Some(live) => live,
+                None => {
+                    !dead && entry.parent.is_none() &&
+                        entry.tag != gimli::DW_TAG_subprogram
+                }
+            };
+            if needed {
+                unit.require_entry(entry.offset);
+            }
+        }
+    }
+
+    let mut out = write::Dwarf::new();
+    {
+        let mut units = out.convert_with_filter(filter)?;
+        while let Some((mut unit, root)) = units.read_unit()? {
+            let dead = is_dead_unit(unit.read_unit, code)?;
+            if let Some(program) = unit.read_line_program(None, None)? {
+                let (program, files) = convert_line_program(program, code)?;
+                unit.set_line_program(program, files);
+            }
+            let root_id = unit.unit.root();
+            convert_attributes(&mut unit, root_id, &root, code)?;
+            let mut entry = root;
+            while let Some(id) = unit.read_entry(&mut entry)? {
+                if let Some(id) = id {
+                    let id = unit.add_entry(Some(id), &entry);
+                    convert_attributes(&mut unit, id, &entry, code)?;
+                }
+            }
+            let root_id = unit.unit.root();
+            if dead && unit.unit.get(root_id).children().len() == 0 {
+                debug!("removing compile unit at {:?}", unit.read_unit.header.offset());
+                unit.skip();
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Returns whether the code that `entry` describes is still there, or `None`
+/// if it doesn't describe any code.
+fn is_live<R: Reader<Offset = usize>>(unit: read::UnitRef<R>,
+                                     entry: &read::DebuggingInformationEntry<R>,
+                                     code: &CodeMap)
+    -> ConvertResult<Option<bool>>
+{
+    let mut ranges = unit.die_ranges(entry)?;
+    let mut any = false;
+    while let Some(range) = ranges.next()? {
+        if code.map(range.begin).is_some() {
+            return Ok(Some(true))
+        }
+        any = true;
+    }
+    Ok(if any { Some(false) } else { None })
+}
+
+/// Returns whether all of the code of `unit` was removed.
+fn is_dead_unit<R: Reader<Offset = usize>>(unit: read::UnitRef<R>, code: &CodeMap)
+    -> ConvertResult<bool>
+{
+    let mut ranges = unit.unit_ranges()?;
+    let mut any = false;
+    while let Some(range) = ranges.next()? {
+        if code.map(range.begin).is_some() {
+            return Ok(false)
+        }
+        any = true;
+    }
+    Ok(any)
+}
+
+fn convert_attributes<R: Reader<Offset = usize>>(unit: &mut ConvertUnit<R>,
+                                                id: write::UnitEntryId,
+                                                entry: &ConvertUnitEntry<R>,
+                                                code: &CodeMap)
+    -> ConvertResult<()>
+{
+    let read_unit = entry.read_unit;
+    let tombstone = !0 >> (64 - 8 * u32::from(read_unit.encoding().address_size));
+    let address = |a: u64| {
+        // A zero address is the base address of a unit rather than code,
+        // since no function body starts at the very start of the section.
+        let a = if a == 0 { 0 } else { code.map(a).unwrap_or(tombstone) };
+        AttributeValue::Address(Address::Constant(a))
+    };
+    let low_pc = match entry.attr_value(gimli::DW_AT_low_pc) {
+        Some(read::AttributeValue::Addr(a)) => Some(a),
+        Some(read::AttributeValue::DebugAddrIndex(i)) => Some(read_unit.address(i)?),
+        _ => None,
+    };
+    for attr in entry.attrs.iter() {
+        let value = match (attr.name(), attr.value()) {
+            // Not supported by gimli, and only an optimization for
+            // consumers.
+            (gimli::DW_AT_GNU_locviews, _) => continue,
+            // `.debug_macro` refers to `.debug_str`, which is rebuilt, so it
+            // is removed.
+            (gimli::DW_AT_macros, _) | (gimli::DW_AT_GNU_macros, _) => continue,
+            (_, read::AttributeValue::Addr(a)) => address(a),
+            (_, read::AttributeValue::DebugAddrIndex(i)) => address(read_unit.address(i)?),
+            (gimli::DW_AT_high_pc, _) => {
+                let length = attr.udata_value().ok_or(ConvertError::InvalidAttributeValue)?;
+                let range = match low_pc {
+                    Some(low) => {
+                        let end = low.checked_add(length).ok_or(ConvertError::InvalidAddress)?;
+                        code.map_range(&read::Range { begin: low, end })
+                    }
+                    None => None,
+                };
+                AttributeValue::Udata(range.map(|r| r.end - r.start).unwrap_or(0))
+            }
+            (_, read::AttributeValue::RangeListsRef(offset)) => {
+                let offset = read_unit.ranges_offset_from_raw(offset);
+                let ranges = convert_ranges(read_unit, offset, code)?;
+                AttributeValue::RangeListRef(unit.unit.ranges.add(ranges))
+            }
+            (_, read::AttributeValue::DebugRngListsIndex(i)) => {
+                let offset = read_unit.ranges_offset(i)?;
+                let ranges = convert_ranges(read_unit, offset, code)?;
+                AttributeValue::RangeListRef(unit.unit.ranges.add(ranges))
+            }
+            (_, read::AttributeValue::LocationListsRef(offset)) => {
+                let locations = convert_locations(unit, read_unit, offset, code)?;
+                AttributeValue::LocationListRef(unit.unit.locations.add(locations))
+            }
+            (_, read::AttributeValue::DebugLocListsIndex(i)) => {
+                let offset = read_unit.locations_offset(i)?;
+                let locations = convert_locations(unit, read_unit, offset, code)?;
+                AttributeValue::LocationListRef(unit.unit.locations.add(locations))
+            }
+            // Any other address is in an expression and refers to linear
+            // memory rather than code.
+            _ => unit.convert_attribute_value(read_unit, attr, &data_address)?,
+        };
+        unit.unit.get_mut(id).set(attr.name(), value);
+    }
+    Ok(())
+}
+
+fn data_address(address: u64) -> Option<Address> {
+    Some(Address::Constant(address))
+}
+
+fn convert_ranges<R: Reader<Offset = usize>>(unit: read::UnitRef<R>,
+                                            offset: gimli::RangeListsOffset,
+                                            code: &CodeMap)
+    -> ConvertResult<RangeList>
+{
+    // Ranges are relative to the unit's base address in DWARF 4, which may
+    // have changed, so start from a base of zero.
+    let mut ranges = vec![write::Range::BaseAddress { address: Address::Constant(0) }];
+    let mut iter = unit.ranges(offset)?;
+    while let Some(range) = iter.next()? {
+        if let Some(range) = code.map_range(&range) {
+            ranges.push(write::Range::StartEnd {
+                begin: Address::Constant(range.start),
+                end: Address::Constant(range.end),
+            });
+        }
+    }
+    Ok(RangeList(ranges))
+}
+
+fn convert_locations<R: Reader<Offset = usize>>(unit: &ConvertUnit<R>,
+                                               read_unit: read::UnitRef<R>,
+                                               offset: gimli::LocationListsOffset,
+                                               code: &CodeMap)
+    -> ConvertResult<LocationList>
+{
+    let mut locations = vec![Location::BaseAddress { address: Address::Constant(0) }];
+    let mut iter = read_unit.locations(offset)?;
+    while let Some(location) = iter.next()? {
+        if let Some(range) = code.map_range(&location.range) {
+            locations.push(Location::StartEnd {
+                begin: Address::Constant(range.start),
+                end: Address::Constant(range.end),
+                data: unit.convert_expression(read_unit, location.data, &data_address)?,
+            });
+        }
+    }
+    Ok(LocationList(locations))
+}
+
+/// Converts a line program, dropping the rows of removed code and moving
+/// the rest to their new addresses.
+fn convert_line_program<R: Reader<Offset = usize>>(mut program: ConvertLineProgram<R>,
+                                                  code: &CodeMap)
+    -> ConvertResult<(LineProgram, Vec<FileId>)>
+{
+    while let Some(sequence) = program.read_sequence()? {
+        let start = match sequence.start {
+            Some(start) => start,
+            None => continue,
+        };
+        let end = match sequence.end {
+            ConvertLineSequenceEnd::Length(length) => {
+                start.checked_add(length).ok_or(ConvertError::InvalidAddress)?
+            }
+            ConvertLineSequenceEnd::Address(address) => address,
+        };
+        let mut new_start = None;
+        let mut last = start;
+        for mut row in sequence.rows {
+            let old = start.checked_add(row.address_offset)
+                .ok_or(ConvertError::InvalidAddress)?;
+            let new = match code.map(old) {
+                Some(new) => new,
+                None => continue,
+            };
+            let base = match new_start {
+                Some(base) => base,
+                None => {
+                    program.set_address(Address::Constant(new));
+                    new_start = Some(new);
+                    new
+                }
+            };
+            row.address_offset = new - base;
+            program.generate_row(row);
+            last = old;
+        }
+        let base = match new_start {
+            Some(base) => base,
+            None => continue,
+        };
+        let new_end = code.map(end)
+            .or_else(|| code.function_end(last))
+            .ok_or(ConvertError::InvalidAddress)?;
+        program.end_sequence(new_end - base);
+    }
+    if program.in_sequence() {
+        return Err(ConvertError::MissingLineEndSequence)
+    }
+    Ok(program.program())
+}
+
+#[cfg(test)]
+mod tests {
+    use gimli::read;
+
+    use super::CodeMap;
+
+    /// Three functions with one- or two-byte size prefixes, of which the
+    /// middle one at 12..20 was removed and the last one shrank.
+    fn code_map() -> CodeMap {
+        let mut map = CodeMap::default();
+        map.add_function(1..10, 1..10, vec![(0, 0), (4, 4)]);
+        map.add_function(21..30, 11..18, vec![(0, 0), (2, 1), (6, 4)]);
+        map
+    }
+
+    #[test]
+    fn maps_instructions() {
+        let map = code_map();
+        assert_eq!(map.map(1), Some(1));
+        assert_eq!(map.map(5), Some(5));
+        assert_eq!(map.map(21), Some(11));
+        assert_eq!(map.map(23), Some(12));
+        assert_eq!(map.map(27), Some(15));
+    }
+
+    #[test]
+    fn maps_within_an_instruction_to_its_start() {
+        let map = code_map();
+        assert_eq!(map.map(3), Some(1));
+        assert_eq!(map.map(26), Some(12));
+    }
+
+    #[test]
+    fn maps_function_ends() {
+        let map = code_map();
+        assert_eq!(map.map(10), Some(10));
+        assert_eq!(map.map(30), Some(18));
+    }
+
+    #[test]
+    fn size_prefixes_are_not_code() {
+        let map = code_map();
+        assert_eq!(map.map(0), None);
+        assert_eq!(map.map(11), None);
+        assert_eq!(map.map(31), None);
+    }
+
+    #[test]
+    fn removed_functions_are_not_mapped() {
+        let map = code_map();
+        assert_eq!(map.map(12), None);
+        assert_eq!(map.map(19), None);
+        assert_eq!(map.map(20), None);
+    }
+
+    #[test]
+    fn maps_ranges() {
+        let map = code_map();
+        let range = |begin, end| map.map_range(&read::Range { begin, end });
+        assert_eq!(range(21, 30), Some(11..18));
+        assert_eq!(range(5, 10), Some(5..10));
+        // A range running into removed code ends with its function.
+        assert_eq!(range(5, 15), Some(5..10));
+        assert_eq!(range(12, 20), None);
+        assert_eq!(range(3, 4), None);
+    }
+}
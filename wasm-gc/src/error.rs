@@ -1,23 +1,124 @@
 use std::error;
 use std::fmt;
-use parity_wasm::elements::Error as ParityWasmError;
+use std::io;
+use wasm_encoder::reencode;
+use wasmparser::BinaryReaderError;
 
 /// The error type for garbage collecting webassembly bytecode.
 #[derive(Debug)]
-pub struct Error(ParityWasmError);
+pub enum Error {
+    /// The module could not be parsed.
+    Parse(BinaryReaderError),
+    /// The module uses something the gc pass can't rewrite, such as a
+    /// component or a section it doesn't know.
+    Unsupported(String),
+    /// An item refers to a section, such as the code or type section, that
+    /// the module doesn't have.
+    MissingSection(&'static str),
+    /// An index is out of bounds of its index space, e.g. a call to a
+    /// function that doesn't exist.
+    IndexOutOfRange {
+        /// The index space, e.g. `"function"` or `"global"`.
+        kind: &'static str,
+        /// The offending index.
+        index: u32,
+    },
+    /// The `name` custom section could not be decoded.
+    MalformedNameSection(String),
+    /// The source map given to `Config::source_map` could not be decoded.
+    MalformedSourceMap(String),
+    /// The module's DWARF debug info could not be decoded or rewritten.
+    MalformedDebugInfo(String),
+    /// After remapping, an item still refers to something that the gc pass
+    /// removed.
+    DanglingReference {
+        /// The index space, e.g. `"function"` or `"global"`.
+        kind: &'static str,
+        /// The original index of the removed item.
+        index: u32,
+    },
+    /// The module has exports that aren't on the configured allowlist.
+    UnlistedExports(Vec<String>),
+    /// The module uses a proposal that its `target_features` section
+    /// doesn't declare.
+    UndeclaredFeature(String),
+    /// The `target_features` section could not be decoded while
+    /// `Config::reject_undeclared_features` is set.
+    MalformedTargetFeatures(String),
+    /// Reading or writing a file failed.
+    Io(io::Error),
+}
 
 impl error::Error for Error {
     fn description(&self) -> &str {
         "webassembly garbage collection failed"
     }
+
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Parse(ref e) => Some(e),
+            Error::Io(ref e) => Some(e),
+            _ => None,
+        }
+    }
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:?}", self.0)
+        match *self {
+            Error::Parse(ref e) => write!(f, "failed to parse module: {}", e),
+            Error::Unsupported(ref what) => write!(f, "unsupported: {}", what),
+            Error::MissingSection(name) => {
+                write!(f, "module has no {} section", name)
+            }
+            Error::IndexOutOfRange { kind, index } => {
+                write!(f, "{} index {} is out of range", kind, index)
+            }
+            Error::MalformedNameSection(ref msg) => {
+                write!(f, "malformed name section: {}", msg)
+            }
+            Error::MalformedSourceMap(ref msg) => {
+                write!(f, "malformed source map: {}", msg)
+            }
+            Error::MalformedDebugInfo(ref msg) => {
+                write!(f, "malformed debug info: {}", msg)
+            }
+            Error::DanglingReference { kind, index } => {
+                write!(f, "reference to removed {} {}", kind, index)
+            }
+            Error::UnlistedExports(ref names) => {
+                write!(f, "module has exports not on the allowlist: {}",
+                       names.join(", "))
+            }
+            Error::UndeclaredFeature(ref msg) => {
+                write!(f, "module uses an undeclared target feature: {}", msg)
+            }
+            Error::MalformedTargetFeatures(ref msg) => {
+                write!(f, "malformed target_features section: {}", msg)
+            }
+            Error::Io(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<BinaryReaderError> for Error {
+    fn from(err: BinaryReaderError) -> Error {
+        Error::Parse(err)
     }
 }
 
-pub fn from(parity: ParityWasmError) -> Error {
-    Error(parity)
+impl From<reencode::Error<Error>> for Error {
+    fn from(err: reencode::Error<Error>) -> Error {
+        match err {
+            reencode::Error::ParseError(e) => Error::Parse(e),
+            reencode::Error::UserError(e) => e,
+            e => Error::Unsupported(e.to_string()),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
 }
@@ -0,0 +1,91 @@
+use wasmparser::{self, BinaryReaderError, Validator, WasmFeatures};
+use error::Error;
+use module::Module;
+use Config;
+
+/// The proposals enabled by each feature of the `target_features` section,
+/// by the names the tool conventions give them.
+const FEATURES: &[(&str, WasmFeatures)] = &[
+    ("atomics", WasmFeatures::THREADS),
+    ("bulk-memory", WasmFeatures::BULK_MEMORY),
+    ("bulk-memory-opt", WasmFeatures::BULK_MEMORY_OPT),
+    ("call-indirect-overlong", WasmFeatures::CALL_INDIRECT_OVERLONG),
+    ("custom-page-sizes", WasmFeatures::CUSTOM_PAGE_SIZES),
+    ("exception-handling", WasmFeatures::EXCEPTIONS.union(WasmFeatures::LEGACY_EXCEPTIONS)),
+    ("extended-const", WasmFeatures::EXTENDED_CONST),
+    ("gc", WasmFeatures::GC.union(WasmFeatures::FUNCTION_REFERENCES)),
+    ("memory64", WasmFeatures::MEMORY64),
+    ("multimemory", WasmFeatures::MULTI_MEMORY),
+    ("multivalue", WasmFeatures::MULTI_VALUE),
+    ("mutable-globals", WasmFeatures::MUTABLE_GLOBAL),
+    ("nontrapping-fptoint", WasmFeatures::SATURATING_FLOAT_TO_INT),
+    ("reference-types", WasmFeatures::REFERENCE_TYPES),
+    ("relaxed-simd", WasmFeatures::RELAXED_SIMD.union(WasmFeatures::SIMD)),
+    ("sign-ext", WasmFeatures::SIGN_EXTENSION),
+    ("simd128", WasmFeatures::SIMD),
+    ("tail-call", WasmFeatures::TAIL_CALL),
+    ("wide-arithmetic", WasmFeatures::WIDE_ARITHMETIC),
+];
+
+/// Checks that `output`, the gc'd `module`, only uses the proposals that the
+/// `target_features` section of `module` declares.
+///
+/// The output is validated rather than the input so that code the gc pass
+/// removed isn't checked. Modules without the section aren't checked. Using
+/// an undeclared feature is logged as a warning, or fails with
+/// `Error::UndeclaredFeature` if `Config::reject_undeclared_features` is set,
+/// in which case a malformed section fails too.
+pub fn check(config: &Config, module: &Module, output: &[u8]) -> Result<(), Error> {
+    let section = match module.custom_section("target_features") {
+        Some(s) => s,
+        None => return Ok(()),
+    };
+    let features = match declared_features(section) {
+        Ok(features) => features,
+        Err(e) if config.reject_undeclared_features => {
+            return Err(Error::MalformedTargetFeatures(e.message().to_string()))
+        }
+        Err(e) => {
+            warn!("not checking target features, malformed target_features section: {}", e);
+            return Ok(())
+        }
+    };
+    let err = match Validator::new_with_features(features).validate_all(output) {
+        Ok(_) => return Ok(()),
+        Err(e) => e,
+    };
+    // Only what's valid with every feature enabled failed because of a
+    // missing feature, validating the module otherwise isn't our job.
+    if Validator::new_with_features(WasmFeatures::all()).validate_all(output).is_err() {
+        debug!("not checking target features of an invalid module: {}", err);
+        return Ok(())
+    }
+    if config.reject_undeclared_features {
+        return Err(Error::UndeclaredFeature(err.to_string()))
+    }
+    warn!("module uses a feature missing from its target_features section: {}", err);
+    Ok(())
+}
+
+/// Returns the proposals enabled by the `target_features` section `s`, on
+/// top of those of the 1.0 spec.
+fn declared_features(s: &wasmparser::CustomSectionReader)
+    -> Result<WasmFeatures, BinaryReaderError>
+{
+    let mut features = WasmFeatures::WASM1;
+    let mut reader = wasmparser::BinaryReader::new(s.data(), s.data_offset());
+    for _ in 0..reader.read_var_u32()? {
+        let prefix = reader.read_u8()?;
+        let name = reader.read_string()?;
+        // `+` declares a used feature and `=` a required one, `-` one that
+        // must not be used.
+        if prefix != b'+' && prefix != b'=' {
+            continue
+        }
+        match FEATURES.iter().find(|&&(feature, _)| feature == name) {
+            Some(&(_, flags)) => features |= flags,
+            None => debug!("unknown target feature: {}", name),
+        }
+    }
+    Ok(features)
+}
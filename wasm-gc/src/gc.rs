@@ -1,857 +1,1394 @@
-use std::collections::{BTreeSet, HashSet};
-use std::str;
+use std::collections::{BTreeMap, BTreeSet};
 
-use parity_wasm::elements::*;
 use rustc_demangle;
+use wasm_encoder;
+use wasm_encoder::reencode::{self, Reencode};
+use wasmparser::{self, DataKind, ElementItems, ElementKind, ExternalKind, Name, Operator};
+use wasmparser::{Parser, Payload};
+use wasmparser::TypeRef;
+use analysis::{Analysis, Item, Remapping};
+use dwarf::{self, CodeMap};
+use error::Error;
+use features;
+use module::{ImportKind, Module};
+use source_map;
 use Config;
 
-pub fn run(config: &mut Config, module: &mut Module) {
-    let analysis = {
-        let mut cx = LiveContext::new(&module);
-
-        cx.blacklist.insert("main");
-        cx.blacklist.insert("__ashldi3");
-        cx.blacklist.insert("__ashlti3");
-        cx.blacklist.insert("__ashrdi3");
-        cx.blacklist.insert("__ashrti3");
-        cx.blacklist.insert("__lshrdi3");
-        cx.blacklist.insert("__lshrti3");
-        cx.blacklist.insert("__floatsisf");
-        cx.blacklist.insert("__floatsidf");
-        cx.blacklist.insert("__floatdidf");
-        cx.blacklist.insert("__floattisf");
-        cx.blacklist.insert("__floattidf");
-        cx.blacklist.insert("__floatunsisf");
-        cx.blacklist.insert("__floatunsidf");
-        cx.blacklist.insert("__floatundidf");
-        cx.blacklist.insert("__floatuntisf");
-        cx.blacklist.insert("__floatuntidf");
-        cx.blacklist.insert("__fixsfsi");
-        cx.blacklist.insert("__fixsfdi");
-        cx.blacklist.insert("__fixsfti");
-        cx.blacklist.insert("__fixdfsi");
-        cx.blacklist.insert("__fixdfdi");
-        cx.blacklist.insert("__fixdfti");
-        cx.blacklist.insert("__fixunssfsi");
-        cx.blacklist.insert("__fixunssfdi");
-        cx.blacklist.insert("__fixunssfti");
-        cx.blacklist.insert("__fixunsdfsi");
-        cx.blacklist.insert("__fixunsdfdi");
-        cx.blacklist.insert("__fixunsdfti");
-        cx.blacklist.insert("__udivsi3");
-        cx.blacklist.insert("__umodsi3");
-        cx.blacklist.insert("__udivmodsi4");
-        cx.blacklist.insert("__udivdi3");
-        cx.blacklist.insert("__udivmoddi4");
-        cx.blacklist.insert("__umoddi3");
-        cx.blacklist.insert("__udivti3");
-        cx.blacklist.insert("__udivmodti4");
-        cx.blacklist.insert("__umodti3");
-        cx.blacklist.insert("memcpy");
-        cx.blacklist.insert("memmove");
-        cx.blacklist.insert("memset");
-        cx.blacklist.insert("memcmp");
-        cx.blacklist.insert("__powisf2");
-        cx.blacklist.insert("__powidf2");
-        cx.blacklist.insert("__addsf3");
-        cx.blacklist.insert("__adddf3");
-        cx.blacklist.insert("__subsf3");
-        cx.blacklist.insert("__subdf3");
-        cx.blacklist.insert("__divsi3");
-        cx.blacklist.insert("__divdi3");
-        cx.blacklist.insert("__divti3");
-        cx.blacklist.insert("__divdf3");
-        cx.blacklist.insert("__divsf3");
-        cx.blacklist.insert("__modsi3");
-        cx.blacklist.insert("__moddi3");
-        cx.blacklist.insert("__modti3");
-        cx.blacklist.insert("__divmodsi4");
-        cx.blacklist.insert("__divmoddi4");
-        cx.blacklist.insert("__muldi3");
-        cx.blacklist.insert("__multi3");
-        cx.blacklist.insert("__muldf3");
-        cx.blacklist.insert("__mulsf3");
-        cx.blacklist.insert("__mulosi4");
-        cx.blacklist.insert("__mulodi4");
-        cx.blacklist.insert("__muloti4");
-        cx.blacklist.insert("rust_eh_personality");
-
-        if let Some(section) = module.export_section() {
-            for (i, entry) in section.entries().iter().enumerate() {
-                cx.add_export_entry(entry, i as u32);
-            }
-        }
-        if let Some(section) = module.import_section() {
-            for (i, entry) in section.entries().iter().enumerate() {
-                debug!("import {:?}", entry);
-                if let External::Memory(_) = *entry.external() {
-                    cx.add_import_entry(entry, i as u32);
+pub fn run(config: &Config, module: &Module) -> Result<Vec<u8>, Error> {
+    let analysis = analyze(config, module)?;
+    remap(config, module, &analysis)
+}
+
+/// Computes which items of `module` are live.
+pub fn analyze(config: &Config, module: &Module) -> Result<Analysis, Error> {
+    let threaded = module.has_shared_memory();
+    if config.reject_unlisted_exports {
+        let unlisted = module.exports
+            .iter()
+            .map(|e| e.name)
+            .filter(|name| !config.allows_export(name, threaded))
+            .map(|name| name.to_string())
+            .collect::<Vec<_>>();
+        if !unlisted.is_empty() {
+            return Err(Error::UnlistedExports(unlisted))
+        }
+    }
+
+    let mut cx = LiveContext::new(module, config);
+    let names = item_names(module)?;
+
+    for (i, entry) in module.exports.iter().enumerate() {
+        cx.analysis.names.insert(Item::Export(i as u32), entry.name.to_string());
+        cx.add_export_entry(i as u32)?;
+    }
+    let mut nfunctions = 0;
+    let mut ntables = 0;
+    let mut nmemories = 0;
+    for entry in module.imports.iter() {
+        debug!("import {:?}", entry);
+        match ImportKind::of(&entry.ty) {
+            // The host can observe what's written to imported memories and
+            // tables, so they stay along with the segments that write to
+            // them. Defined ones are only kept if something refers to them
+            // or one of their segments may trap, see `add_active_segments`.
+            ImportKind::Memory => {
+                cx.add_memory(nmemories)?;
+                nmemories += 1;
+            }
+            ImportKind::Table => {
+                cx.add_table(ntables)?;
+                ntables += 1;
+            }
+            ImportKind::Function => {
+                let name = format!("{}.{}", entry.module, entry.name);
+                cx.analysis.names.insert(Item::Function(nfunctions), name);
+                if config.keeps_name(entry.name) {
+                    cx.current.push(Item::Keep);
+                    cx.add_function(nfunctions)?;
+                    cx.current.pop();
                 }
+                nfunctions += 1;
             }
+            _ => {}
         }
-        if let Some(section) = module.data_section() {
-            for entry in section.entries() {
-                cx.add_data_segment(entry);
-            }
+    }
+    // Declared segments only declare functions for `ref.func`, keep them
+    // as they are.
+    for (i, entry) in module.elements.iter().enumerate() {
+        if let ElementKind::Declared = entry.kind {
+            cx.add_element_segment(i as u32)?;
         }
-        if let Some(tables) = module.table_section() {
-            for i in 0..tables.entries().len() as u32 {
-                cx.add_table(i);
+    }
+    if let Some(i) = module.start {
+        cx.mark(Item::Start);
+        cx.current.push(Item::Start);
+        cx.add_function(i)?;
+        cx.current.pop();
+    }
+    if !config.keep_functions.is_empty() || !config.keep_patterns.is_empty() {
+        cx.mark(Item::Keep);
+    }
+    cx.current.push(Item::Keep);
+    for &i in config.keep_functions.iter() {
+        cx.add_function(i)?;
+    }
+    if !config.keep_patterns.is_empty() {
+        for (&item, name) in names.iter() {
+            let i = match item {
+                Item::Function(i) => i,
+                _ => continue,
+            };
+            let demangled = rustc_demangle::demangle(name).to_string();
+            if config.keeps_name(name) || config.keeps_name(&demangled) {
+                cx.add_function(i)?;
             }
         }
-        if let Some(elements) = module.elements_section() {
-            for seg in elements.entries() {
-                cx.add_element_segment(seg);
-            }
+    }
+    cx.current.pop();
+    cx.add_active_segments()?;
+
+    for (item, name) in names {
+        cx.analysis.names.insert(item, name.to_string());
+    }
+    let mut analysis = cx.analysis;
+    analysis.remapping = remapping(module, &analysis);
+    Ok(analysis)
+}
+
+/// Computes how the items that `analysis` keeps are renumbered.
+fn remapping(module: &Module, analysis: &Analysis) -> Remapping {
+    let mut nfunctions = 0;
+    let mut functions = Vec::new();
+    let mut nglobals = 0;
+    let mut globals = Vec::new();
+    let mut ntables = 0;
+    let mut tables = Vec::new();
+    let mut nmemories = 0;
+    let mut memories = Vec::new();
+    let mut ntags = 0;
+    let mut tags = Vec::new();
+
+    let mut removed = 0;
+    let mut types = Vec::new();
+    for i in 0..(module.types.len() as u32) {
+        if analysis.types.contains(&i) {
+            types.push(Some(i - removed));
+        } else {
+            debug!("gc type {}", i);
+            types.push(None);
+            removed += 1;
         }
-        if let Some(i) = module.start_section() {
-            cx.add_function(i);
+    }
+    for (i, import) in module.imports.iter().enumerate() {
+        let (dst, ndst) = match ImportKind::of(&import.ty) {
+            ImportKind::Function => (&mut functions, &mut nfunctions),
+            ImportKind::Table => (&mut tables, &mut ntables),
+            ImportKind::Memory => (&mut memories, &mut nmemories),
+            ImportKind::Global => (&mut globals, &mut nglobals),
+            ImportKind::Tag => (&mut tags, &mut ntags),
+        };
+        if analysis.imports.contains(&(i as u32)) {
+            dst.push(Some(*ndst));
+            *ndst += 1;
+        } else {
+            debug!("gc import {}", i);
+            dst.push(None);
         }
-        cx.analysis
-    };
+    }
+    for i in 0..(module.functions.len() as u32) {
+        if analysis.codes.contains(&i) {
+            functions.push(Some(nfunctions));
+            nfunctions += 1;
+        } else {
+            debug!("gc function {}", i);
+            functions.push(None);
+        }
+    }
+    for i in 0..(module.globals.len() as u32) {
+        if analysis.globals.contains(&i) {
+            globals.push(Some(nglobals));
+            nglobals += 1;
+        } else {
+            debug!("gc global {}", i);
+            globals.push(None);
+        }
+    }
+    for i in 0..(module.tables.len() as u32) {
+        if analysis.tables.contains(&i) {
+            tables.push(Some(ntables));
+            ntables += 1;
+        } else {
+            debug!("gc table {}", i);
+            tables.push(None);
+        }
+    }
+    for i in 0..(module.memories.len() as u32) {
+        if analysis.memories.contains(&i) {
+            memories.push(Some(nmemories));
+            nmemories += 1;
+        } else {
+            debug!("gc memory {}", i);
+            memories.push(None);
+        }
+    }
+    for i in 0..(module.tags.len() as u32) {
+        if analysis.tags.contains(&i) {
+            tags.push(Some(ntags));
+            ntags += 1;
+        } else {
+            debug!("gc tag {}", i);
+            tags.push(None);
+        }
+    }
+    let mut removed = 0;
+    let mut data_segments = Vec::new();
+    for i in 0..(module.data_segments.len() as u32) {
+        if analysis.data_segments.contains(&i) {
+            data_segments.push(Some(i - removed));
+        } else {
+            debug!("gc data segment {}", i);
+            data_segments.push(None);
+            removed += 1;
+        }
+    }
+    let mut removed = 0;
+    let mut element_segments = Vec::new();
+    for i in 0..(module.elements.len() as u32) {
+        if analysis.element_segments.contains(&i) {
+            element_segments.push(Some(i - removed));
+        } else {
+            debug!("gc element segment {}", i);
+            element_segments.push(None);
+            removed += 1;
+        }
+    }
 
-    let cx = RemapContext::new(&module, &analysis, config);
-    for i in (0..module.sections().len()).rev() {
-        let retain = match module.sections_mut()[i] {
-            Section::Unparsed { .. } => {
-                info!("unparsed section");
-                continue
+    Remapping {
+        functions,
+        globals,
+        types,
+        tables,
+        memories,
+        tags,
+        data_segments,
+        element_segments,
+    }
+}
+
+/// Encodes everything of `module` that's live according to `analysis`,
+/// with the indices of what's left remapped.
+pub fn remap(config: &Config, module: &Module, analysis: &Analysis)
+    -> Result<Vec<u8>, Error>
+{
+    encode(config, module, analysis, false).map(|(output, _, _)| output)
+}
+
+/// Like `remap`, but also returns the source map `source_map` of the input
+/// rewritten for the output.
+pub fn remap_with_source_map(config: &Config,
+                             module: &Module,
+                             analysis: &Analysis,
+                             source_map: &str)
+    -> Result<(Vec<u8>, String), Error>
+{
+    let (output, code, code_offset) = encode(config, module, analysis, true)?;
+    let source_map = source_map::rewrite(source_map, &code, module.code_offset, code_offset)?;
+    Ok((output, source_map))
+}
+
+/// Encodes the output of `remap`, along with where its code ended up and
+/// the offset of its code section.
+///
+/// The code map is only filled in if `source_map` is set or the module has
+/// DWARF to rewrite.
+fn encode(config: &Config, module: &Module, analysis: &Analysis, source_map: bool)
+    -> Result<(Vec<u8>, CodeMap, usize), Error>
+{
+    let mut cx = RemapContext::new(module, analysis, config, source_map);
+    let mut out = wasm_encoder::Module::new();
+    // The code section is encoded first since rewriting the DWARF needs to
+    // know where the code ended up, and DWARF may come before the code.
+    let code = cx.remap_code_section()?;
+    let mut wrote_elements = false;
+    let mut wrote_dwarf = false;
+    let mut code_offset = 0;
+    let mut wrote_producers = false;
+    for payload in Parser::new(0).parse_all(module.data) {
+        let payload = payload?;
+        // The element section may have to declare functions for `ref.func`
+        // even if the input has none, so it's written where it would be.
+        let elements_due = matches!(payload,
+                                    Payload::ElementSection(_) |
+                                    Payload::DataCountSection { .. } |
+                                    Payload::CodeSectionStart { .. } |
+                                    Payload::DataSection(_));
+        if elements_due && !wrote_elements {
+            let section = cx.remap_element_section()?;
+            push_section(&mut out, &section, section.is_empty());
+            wrote_elements = true;
+        }
+        match payload {
+            Payload::CustomSection(s) => {
+                if s.name() == "name" {
+                    let section = cx.remap_name_section(&s)?;
+                    out.section(&section);
+                } else if dwarf::is_debug_section(s.name()) {
+                    // All the DWARF sections are rewritten together, they go
+                    // where the first one was.
+                    if wrote_dwarf {
+                        continue
+                    }
+                    wrote_dwarf = true;
+                    // Debug info that wasn't rewritten would describe the
+                    // wrong code, so failing to rewrite it is an error.
+                    let sections = dwarf::rewrite(module, &cx.code_map)
+                        .map_err(Error::MalformedDebugInfo)?;
+                    for (name, data) in sections {
+                        out.section(&wasm_encoder::CustomSection {
+                            name: name.into(),
+                            data: data.into(),
+                        });
+                    }
+                } else if s.name() == "producers" && config.record_processed_by {
+                    // A second `producers` section would be invalid, so one
+                    // that can't be decoded is kept as it is.
+                    match processed_by(Some(&s)) {
+                        Ok(section) => out.section(&section),
+                        Err(e) => {
+                            warn!("copying malformed producers section: {}", e);
+                            out.section(&cx.custom_section(s)?)
+                        }
+                    };
+                    wrote_producers = true;
+                } else {
+                    info!("copying custom section: {}", s.name());
+                    out.section(&cx.custom_section(s)?);
+                }
             }
-            Section::Custom(ref mut s) if s.name() == "name" => {
-                cx.remap_name_section(s);
-                continue
+            Payload::TypeSection(s) => {
+                let section = cx.remap_type_section(s)?;
+                push_section(&mut out, &section, section.is_empty());
             }
-            Section::Custom(ref s) => {
-                info!("skipping custom section: {}", s.name());
-                continue
+            Payload::ImportSection(_) => {
+                let section = cx.remap_import_section()?;
+                push_section(&mut out, &section, section.is_empty());
             }
-            Section::Type(ref mut s) => cx.remap_type_section(s),
-            Section::Import(ref mut s) => cx.remap_import_section(s),
-            Section::Function(ref mut s) => cx.remap_function_section(s),
-            Section::Table(ref mut s) => cx.remap_table_section(s),
-            Section::Memory(ref mut s) => cx.remap_memory_section(s),
-            Section::Global(ref mut s) => cx.remap_global_section(s),
-            Section::Export(ref mut s) => cx.remap_export_section(s),
-            Section::Start(ref mut i) => { cx.remap_function_idx(i); true }
-            Section::Element(ref mut s) => cx.remap_element_section(s),
-            Section::Code(ref mut s) => cx.remap_code_section(s),
-            Section::Data(ref mut s) => cx.remap_data_section(s),
-        };
-        if !retain {
-            debug!("remove empty section");
-            module.sections_mut().remove(i);
+            Payload::FunctionSection(_) => {
+                let section = cx.remap_function_section()?;
+                push_section(&mut out, &section, section.is_empty());
+            }
+            Payload::TableSection(_) => {
+                let section = cx.remap_table_section()?;
+                push_section(&mut out, &section, section.is_empty());
+            }
+            Payload::MemorySection(_) => {
+                let section = cx.remap_memory_section()?;
+                push_section(&mut out, &section, section.is_empty());
+            }
+            Payload::TagSection(_) => {
+                let section = cx.remap_tag_section()?;
+                push_section(&mut out, &section, section.is_empty());
+            }
+            Payload::GlobalSection(_) => {
+                let section = cx.remap_global_section()?;
+                push_section(&mut out, &section, section.is_empty());
+            }
+            Payload::ExportSection(_) => {
+                let section = cx.remap_export_section()?;
+                push_section(&mut out, &section, section.is_empty());
+            }
+            Payload::StartSection { func, .. } => {
+                let function_index = cx.start_section(func)?;
+                out.section(&wasm_encoder::StartSection { function_index });
+            }
+            Payload::DataCountSection { count, .. } => {
+                let count = cx.data_count(count)?;
+                out.section(&wasm_encoder::DataCountSection { count });
+            }
+            Payload::DataSection(_) => {
+                let section = cx.remap_data_section()?;
+                push_section(&mut out, &section, section.is_empty());
+            }
+            Payload::CodeSectionStart { .. } => {
+                let size = leb128_len(code.len() as usize) + code.byte_len();
+                code_offset = out.len() + 1 + leb128_len(size);
+                push_section(&mut out, &code, code.is_empty());
+            }
+            _ => {}
         }
     }
+    if config.record_processed_by && !wrote_producers {
+        out.section(&processed_by(None)?);
+    }
+    let output = out.finish();
+    features::check(config, module, &output)?;
+    Ok((output, cx.code_map, code_offset))
 }
 
-#[derive(Default)]
-struct Analysis {
-    codes: BTreeSet<u32>,
-    tables: BTreeSet<u32>,
-    memories: BTreeSet<u32>,
-    globals: BTreeSet<u32>,
-    types: BTreeSet<u32>,
-    imports: BTreeSet<u32>,
-    exports: BTreeSet<u32>,
-}
-
-enum Memories<'a> {
-    Exported(&'a MemorySection),
-    Imported(&'a MemoryType),
+/// Encodes the `producers` section `s`, or a new one if there's none, with
+/// this version of wasm-gc in its `processed-by` field.
+fn processed_by(s: Option<&wasmparser::CustomSectionReader>)
+    -> Result<wasm_encoder::ProducersSection, Error>
+{
+    const NAME: &str = "wasm-gc";
+    const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+    let mut res = wasm_encoder::ProducersSection::new();
+    let mut recorded = false;
+    if let Some(s) = s {
+        let data = wasmparser::BinaryReader::new(s.data(), s.data_offset());
+        for field in wasmparser::ProducersSectionReader::new(data)? {
+            let field = field?;
+            let processed_by = field.name == "processed-by";
+            let mut values = wasm_encoder::ProducersField::new();
+            for value in field.values {
+                let value = value?;
+                if processed_by && value.name == NAME {
+                    values.value(NAME, VERSION);
+                    recorded = true;
+                } else {
+                    values.value(value.name, value.version);
+                }
+            }
+            if processed_by && !recorded {
+                values.value(NAME, VERSION);
+                recorded = true;
+            }
+            res.field(field.name, &values);
+        }
+    }
+    if !recorded {
+        res.field("processed-by", wasm_encoder::ProducersField::new().value(NAME, VERSION));
+    }
+    Ok(res)
 }
 
-impl<'a> Memories<'a> {
-    fn has_entry(&self, idx: usize) -> bool {
-        match *self {
-            Memories::Exported(memory_section) => idx < memory_section.entries().len(),
-            Memories::Imported(_) => idx == 0,
-        }
+fn push_section<S>(module: &mut wasm_encoder::Module, section: &S, is_empty: bool)
+where
+    S: wasm_encoder::Section,
+{
+    if is_empty {
+        debug!("remove empty section");
+        return
     }
+    module.section(section);
 }
 
 struct LiveContext<'a> {
-    blacklist: HashSet<&'static str>,
-    function_section: Option<&'a FunctionSection>,
-    type_section: Option<&'a TypeSection>,
-    code_section: Option<&'a CodeSection>,
-    table_section: Option<&'a TableSection>,
-    memories: Option<Memories<'a>>,
-    global_section: Option<&'a GlobalSection>,
-    import_section: Option<&'a ImportSection>,
+    config: &'a Config,
+    module: &'a Module<'a>,
+    imported_functions: u32,
+    imported_tables: u32,
+    imported_memories: u32,
+    imported_globals: u32,
+    imported_tags: u32,
+    /// Whether the module has a shared memory.
+    threaded: bool,
+    /// The items whose references are currently being added, innermost
+    /// last.
+    current: Vec<Item>,
     analysis: Analysis,
 }
 
 impl<'a> LiveContext<'a> {
-    fn new(module: &'a Module) -> LiveContext<'a> {
-        let memories = module.memory_section().map(Memories::Exported).or_else(|| {
-            if let Some(import_section) = module.import_section() {
-                for entry in import_section.entries() {
-                    if let External::Memory(ref memory_type) = *entry.external() {
-                        return Some(Memories::Imported(memory_type));
-                    }
-                }
-            }
-
-            None
-        });
-
+    fn new(module: &'a Module<'a>, config: &'a Config) -> LiveContext<'a> {
         LiveContext {
-            blacklist: HashSet::new(),
-            function_section: module.function_section(),
-            type_section: module.type_section(),
-            code_section: module.code_section(),
-            table_section: module.table_section(),
-            memories: memories,
-            global_section: module.global_section(),
-            import_section: module.import_section(),
+            config,
+            module,
+            imported_functions: module.num_imports(ImportKind::Function),
+            imported_tables: module.num_imports(ImportKind::Table),
+            imported_memories: module.num_imports(ImportKind::Memory),
+            imported_globals: module.num_imports(ImportKind::Global),
+            imported_tags: module.num_imports(ImportKind::Tag),
+            threaded: module.has_shared_memory(),
+            current: Vec::new(),
             analysis: Analysis::default(),
         }
     }
 
-    fn add_function(&mut self, mut idx: u32) {
-        if let Some(imports) = self.import_section {
-            if idx < imports.functions() as u32 {
-                debug!("adding import: {}", idx);
-                let (i, import) = imports.entries()
-                    .iter()
-                    .enumerate()
-                    .filter(|&(_, i)| {
-                        match *i.external() {
-                            External::Function(_) => true,
-                            _ => false,
-                        }
-                    })
-                    .skip(idx as usize)
-                    .next()
-                    .expect("expected an imported function with this index");
-                let i = i as u32;
-                self.analysis.imports.insert(i);
-                return self.add_import_entry(import, i);
+    /// Records that `item` is live because of the item currently being
+    /// added, or because it's a root if there is none.
+    fn mark(&mut self, item: Item) {
+        match self.current.last() {
+            Some(&from) => {
+                self.analysis.referrers
+                    .entry(item)
+                    .or_default()
+                    .insert(from);
+            }
+            None => {
+                self.analysis.roots.insert(item);
             }
-            idx -= imports.functions() as u32;
         }
+    }
 
-        if !self.analysis.codes.insert(idx) {
-            return
+    /// Adds the import that `idx` of the index space `kind` refers to.
+    fn add_imported(&mut self, kind: ImportKind, item: Item, idx: u32) -> Result<(), Error> {
+        let module = self.module;
+        let (i, import) = module.import(kind, idx)
+            .ok_or(Error::IndexOutOfRange { kind: kind_name(kind), index: idx })?;
+        if !self.analysis.imports.insert(i) {
+            return Ok(())
         }
-
-        debug!("adding function: {}", idx);
-        let functions = self.function_section.expect("no functions section");
-        self.add_type(functions.entries()[idx as usize].type_ref());
-        let codes = self.code_section.expect("no codes section");
-        self.add_func_body(&codes.bodies()[idx as usize]);
+        self.current.push(item);
+        self.add_import_entry(&import.ty)?;
+        self.current.pop();
+        Ok(())
     }
 
-    fn add_table(&mut self, mut idx: u32) {
-        if let Some(imports) = self.import_section {
-            let imported_tables = imports.entries()
-                .iter()
-                .filter(|i| {
-                    match *i.external() {
-                        External::Table(_) => true,
-                        _ => false,
-                    }
-                })
-                .count();
-            let imported_tables = imported_tables as u32;
-            if idx < imported_tables {
-                debug!("adding table import: {}", idx);
-                let (i, import) = imports.entries()
-                    .iter()
-                    .enumerate()
-                    .filter(|&(_, i)| {
-                        match *i.external() {
-                            External::Table(_) => true,
-                            _ => false,
-                        }
-                    })
-                    .skip(idx as usize)
-                    .next()
-                    .expect("expected an imported table with this index");
-                let i = i as u32;
-                self.analysis.imports.insert(i);
-                return self.add_import_entry(import, i);
-            }
-            idx -= imported_tables;
+    fn add_function(&mut self, idx: u32) -> Result<(), Error> {
+        let item = Item::Function(idx);
+        self.mark(item);
+        if idx < self.imported_functions {
+            debug!("adding import: {}", idx);
+            return self.add_imported(ImportKind::Function, item, idx)
         }
-        if !self.analysis.tables.insert(idx) {
-            return
+        let defined = idx - self.imported_functions;
+        if !self.analysis.codes.insert(defined) {
+            return Ok(())
         }
-        let tables = self.table_section.expect("no table section");
-        let table = &tables.entries()[idx as usize];
-        drop(table);
+
+        debug!("adding function: {}", defined);
+        let module = self.module;
+        let ty = *module.functions
+            .get(defined as usize)
+            .ok_or_else(|| out_of_range("function", module.functions.len(), idx))?;
+        let body = module.code
+            .get(defined as usize)
+            .ok_or_else(|| out_of_range("code", module.code.len(), defined))?;
+        self.current.push(item);
+        self.add_type(ty)?;
+        self.add_refs(|r| {
+            r.parse_function_body(&mut wasm_encoder::CodeSection::new(), body.clone())
+        })?;
+        self.current.pop();
+        Ok(())
     }
 
-    fn add_memory(&mut self, idx: u32) {
-        if !self.analysis.memories.insert(idx) {
-            return
+    fn add_table(&mut self, idx: u32) -> Result<(), Error> {
+        let item = Item::Table(idx);
+        self.mark(item);
+        if idx < self.imported_tables {
+            debug!("adding table import: {}", idx);
+            return self.add_imported(ImportKind::Table, item, idx)
+        }
+        let defined = idx - self.imported_tables;
+        if !self.analysis.tables.insert(defined) {
+            return Ok(())
         }
-        let memories = self.memories.as_ref().expect("no memory section or imported memory");
-        assert!(memories.has_entry(idx as usize));
+        let module = self.module;
+        let table = module.tables
+            .get(defined as usize)
+            .ok_or_else(|| out_of_range("table", module.tables.len(), idx))?;
+        self.current.push(item);
+        self.add_refs(|r| {
+            r.parse_table(&mut wasm_encoder::TableSection::new(), table.clone())
+        })?;
+        self.current.pop();
+        Ok(())
     }
 
-    fn add_global(&mut self, mut idx: u32) {
-        if let Some(imports) = self.import_section {
-            if idx < imports.globals() as u32 {
-                debug!("adding global import: {}", idx);
-                let (i, import) = imports.entries()
-                    .iter()
-                    .enumerate()
-                    .filter(|&(_, i)| {
-                        match *i.external() {
-                            External::Global(_) => true,
-                            _ => false,
-                        }
-                    })
-                    .skip(idx as usize)
-                    .next()
-                    .expect("expected an imported global with this index");
-                let i = i as u32;
-                self.analysis.imports.insert(i);
-                return self.add_import_entry(import, i);
-            }
-            idx -= imports.globals() as u32;
+    fn add_memory(&mut self, idx: u32) -> Result<(), Error> {
+        let item = Item::Memory(idx);
+        self.mark(item);
+        if idx < self.imported_memories {
+            debug!("adding memory import: {}", idx);
+            return self.add_imported(ImportKind::Memory, item, idx)
+        }
+        let defined = idx - self.imported_memories;
+        if !self.analysis.memories.insert(defined) {
+            return Ok(())
+        }
+        if defined as usize >= self.module.memories.len() {
+            return Err(out_of_range("memory", self.module.memories.len(), idx))
         }
+        Ok(())
+    }
 
-        if !self.analysis.globals.insert(idx) {
-            return
+    fn add_global(&mut self, idx: u32) -> Result<(), Error> {
+        let item = Item::Global(idx);
+        self.mark(item);
+        if idx < self.imported_globals {
+            debug!("adding global import: {}", idx);
+            return self.add_imported(ImportKind::Global, item, idx)
+        }
+        let defined = idx - self.imported_globals;
+        if !self.analysis.globals.insert(defined) {
+            return Ok(())
         }
-        let globals = self.global_section.expect("no global section");
-        let global = &globals.entries()[idx as usize];
-        self.add_global_type(global.global_type());
-        self.add_init_expr(global.init_expr());
+        let module = self.module;
+        let global = module.globals
+            .get(defined as usize)
+            .ok_or_else(|| out_of_range("global", module.globals.len(), idx))?;
+        self.current.push(item);
+        self.add_refs(|r| {
+            r.parse_global(&mut wasm_encoder::GlobalSection::new(), global.clone())
+        })?;
+        self.current.pop();
+        Ok(())
     }
 
-    fn add_global_type(&mut self, t: &GlobalType) {
-        self.add_value_type(&t.content_type());
+    fn add_tag(&mut self, idx: u32) -> Result<(), Error> {
+        let item = Item::Tag(idx);
+        self.mark(item);
+        if idx < self.imported_tags {
+            debug!("adding tag import: {}", idx);
+            return self.add_imported(ImportKind::Tag, item, idx)
+        }
+        let defined = idx - self.imported_tags;
+        if !self.analysis.tags.insert(defined) {
+            return Ok(())
+        }
+        let module = self.module;
+        let tag = *module.tags
+            .get(defined as usize)
+            .ok_or_else(|| out_of_range("tag", module.tags.len(), idx))?;
+        self.current.push(item);
+        self.add_refs(|r| r.tag_type(tag).map(|_| ()))?;
+        self.current.pop();
+        Ok(())
     }
 
-    fn add_init_expr(&mut self, t: &InitExpr) {
-        for opcode in t.code() {
-            self.add_opcode(opcode);
+    fn add_type(&mut self, idx: u32) -> Result<(), Error> {
+        let item = Item::Type(idx);
+        self.mark(item);
+        if self.analysis.types.contains(&idx) {
+            return Ok(())
+        }
+        let module = self.module;
+        let group = module.rec_group(idx)
+            .ok_or_else(|| out_of_range("type", module.types.len(), idx))?;
+        // A recursion group is a single type as far as type equality is
+        // concerned, so removing some of its members would change the
+        // identity of the others. The whole group is added before any of its
+        // members is walked so that walking one doesn't revisit the group.
+        self.current.push(item);
+        for i in group.clone().filter(|&i| i != idx) {
+            self.mark(Item::Type(i));
+        }
+        self.current.pop();
+        self.analysis.types.extend(group.clone());
+        for i in group {
+            let ty = &module.types[i as usize];
+            self.current.push(Item::Type(i));
+            self.add_refs(|r| r.sub_type(ty.clone()).map(|_| ()))?;
+            self.current.pop();
         }
+        Ok(())
     }
 
-    fn add_type(&mut self, idx: u32) {
-        if !self.analysis.types.insert(idx) {
-            return
+    fn add_export_entry(&mut self, idx: u32) -> Result<(), Error> {
+        let module = self.module;
+        let entry = &module.exports[idx as usize];
+        if self.config.drops_export(entry.name, self.threaded) {
+            debug!("dropping export {}", entry.name);
+            return Ok(())
         }
-        let types = self.type_section.expect("no types section");
-        match types.types()[idx as usize] {
-            Type::Function(ref f) => {
-                for param in f.params() {
-                    self.add_value_type(param);
-                }
-                if let Some(ref ret) = f.return_type() {
-                    self.add_value_type(ret);
-                }
-            }
+        self.analysis.exports.insert(idx);
+        self.mark(Item::Export(idx));
+        self.current.push(Item::Export(idx));
+        match entry.kind {
+            ExternalKind::Func | ExternalKind::FuncExact => self.add_function(entry.index)?,
+            ExternalKind::Table => self.add_table(entry.index)?,
+            ExternalKind::Memory => self.add_memory(entry.index)?,
+            ExternalKind::Global => self.add_global(entry.index)?,
+            ExternalKind::Tag => self.add_tag(entry.index)?,
         }
+        self.current.pop();
+        Ok(())
     }
 
-    fn add_value_type(&mut self, value: &ValueType) {
-        match *value {
-            ValueType::I32 => {}
-            ValueType::I64 => {}
-            ValueType::F32 => {}
-            ValueType::F64 => {}
+    fn add_import_entry(&mut self, ty: &TypeRef) -> Result<(), Error> {
+        match *ty {
+            TypeRef::Func(i) | TypeRef::FuncExact(i) => self.add_type(i),
+            TypeRef::Table(t) => self.add_refs(|r| r.table_type(t).map(|_| ())),
+            TypeRef::Global(g) => self.add_refs(|r| r.global_type(g).map(|_| ())),
+            TypeRef::Tag(t) => self.add_type(t.func_type_idx),
+            TypeRef::Memory(_) => Ok(()),
         }
     }
 
-    fn add_func_body(&mut self, body: &FuncBody) {
-        for local in body.locals() {
-            self.add_value_type(&local.value_type());
+    fn add_data_segment(&mut self, idx: u32) -> Result<(), Error> {
+        let item = Item::DataSegment(idx);
+        self.mark(item);
+        if !self.analysis.data_segments.insert(idx) {
+            return Ok(())
+        }
+        let module = self.module;
+        let data = module.data_segments
+            .get(idx as usize)
+            .ok_or_else(|| out_of_range("data segment", module.data_segments.len(), idx))?;
+        self.current.push(item);
+        if let DataKind::Active { memory_index, ref offset_expr } = data.kind {
+            self.add_memory(memory_index)?;
+            self.add_refs(|r| r.const_expr(offset_expr.clone()).map(|_| ()))?;
         }
-        self.add_opcodes(body.code());
+        self.current.pop();
+        Ok(())
     }
 
-    fn add_opcodes(&mut self, code: &Opcodes) {
-        for opcode in code.elements() {
-            self.add_opcode(opcode);
+    fn add_element_segment(&mut self, idx: u32) -> Result<(), Error> {
+        let item = Item::ElementSegment(idx);
+        self.mark(item);
+        if !self.analysis.element_segments.insert(idx) {
+            return Ok(())
         }
+        let module = self.module;
+        let seg = module.elements
+            .get(idx as usize)
+            .ok_or_else(|| out_of_range("element segment", module.elements.len(), idx))?;
+        self.current.push(item);
+        if let ElementKind::Active { table_index, ref offset_expr } = seg.kind {
+            self.add_table(table_index.unwrap_or(0))?;
+            self.add_refs(|r| r.const_expr(offset_expr.clone()).map(|_| ()))?;
+        }
+        self.add_refs(|r| r.element_items(seg.items.clone()).map(|_| ()))?;
+        self.current.pop();
+        Ok(())
     }
 
-    fn add_opcode(&mut self, code: &Opcode) {
-        match *code {
-            Opcode::Block(ref b) |
-            Opcode::Loop(ref b) |
-            Opcode::If(ref b) => self.add_block_type(b),
-            Opcode::Call(f) => self.add_function(f),
-            Opcode::CallIndirect(t, _) => self.add_type(t),
-            Opcode::GetGlobal(i) |
-            Opcode::SetGlobal(i) => self.add_global(i),
-            _ => {}
+    /// Adds the active segments that initialize live memories and tables.
+    ///
+    /// Passive segments are only live if live code refers to them. An
+    /// element segment can make more memories and tables live through the
+    /// functions it places, so this repeats until no more segments are added.
+    ///
+    /// The active segments of a dead memory or table are only removed if
+    /// they're known to fit in it. A segment that might not makes
+    /// instantiation trap, so it's kept, along with its memory or table, to
+    /// keep trapping.
+    fn add_active_segments(&mut self) -> Result<(), Error> {
+        let module = self.module;
+        loop {
+            let mut added = false;
+            for (i, data) in module.data_segments.iter().enumerate() {
+                let i = i as u32;
+                let (memory, offset) = match data.kind {
+                    DataKind::Active { memory_index, ref offset_expr } => {
+                        (memory_index, offset_expr)
+                    }
+                    DataKind::Passive => continue,
+                };
+                if self.analysis.data_segments.contains(&i) ||
+                    (!self.analysis.is_live(Item::Memory(memory)) &&
+                     self.data_fits(memory, offset, data.data.len()))
+                {
+                    continue
+                }
+                self.current.push(Item::Memory(memory));
+                self.add_data_segment(i)?;
+                self.current.pop();
+                added = true;
+            }
+            for (i, seg) in module.elements.iter().enumerate() {
+                let i = i as u32;
+                let (table, offset) = match seg.kind {
+                    ElementKind::Active { table_index, ref offset_expr } => {
+                        (table_index.unwrap_or(0), offset_expr)
+                    }
+                    _ => continue,
+                };
+                let len = match seg.items {
+                    ElementItems::Functions(ref r) => r.count(),
+                    ElementItems::Expressions(_, ref r) => r.count(),
+                };
+                if self.analysis.element_segments.contains(&i) ||
+                    (!self.analysis.is_live(Item::Table(table)) &&
+                     self.elements_fit(table, offset, len))
+                {
+                    continue
+                }
+                self.current.push(Item::Table(table));
+                self.add_element_segment(i)?;
+                self.current.pop();
+                added = true;
+            }
+            if !added {
+                return Ok(())
+            }
         }
     }
 
-    fn add_block_type(&mut self, bt: &BlockType) {
-        match *bt {
-            BlockType::Value(ref v) => self.add_value_type(v),
-            BlockType::NoResult => {}
+    /// Returns whether `len` bytes at `offset` are known to fit in the
+    /// initial size of `memory`.
+    fn data_fits(&self, memory: u32, offset: &wasmparser::ConstExpr, len: usize) -> bool {
+        if memory < self.imported_memories {
+            return false
+        }
+        let ty = match self.module.memories.get((memory - self.imported_memories) as usize) {
+            Some(ty) => ty,
+            None => return false,
+        };
+        let page_size = 1u64 << ty.page_size_log2.unwrap_or(16);
+        let size = ty.initial.checked_mul(page_size);
+        match const_offset(offset).and_then(|offset| offset.checked_add(len as u64)) {
+            Some(end) => size.is_none_or(|size| end <= size),
+            None => false,
         }
     }
 
-    fn add_export_entry(&mut self, entry: &ExportEntry, idx: u32) {
-        if self.blacklist.contains(entry.field()) {
-            return
+    /// Returns whether `len` elements at `offset` are known to fit in the
+    /// initial size of `table`.
+    fn elements_fit(&self, table: u32, offset: &wasmparser::ConstExpr, len: u32) -> bool {
+        if table < self.imported_tables {
+            return false
         }
-        self.analysis.exports.insert(idx);
-        match *entry.internal() {
-            Internal::Function(i) => self.add_function(i),
-            Internal::Table(i) => self.add_table(i),
-            Internal::Memory(i) => self.add_memory(i),
-            Internal::Global(i) => self.add_global(i),
+        let ty = match self.module.tables.get((table - self.imported_tables) as usize) {
+            Some(table) => table.ty,
+            None => return false,
+        };
+        match const_offset(offset).and_then(|offset| offset.checked_add(u64::from(len))) {
+            Some(end) => end <= ty.initial,
+            None => false,
         }
     }
 
-    fn add_import_entry(&mut self, entry: &ImportEntry, idx: u32) {
-        match *entry.external() {
-            External::Function(i) => self.add_type(i),
-            External::Table(_) => {},
-            External::Memory(_) => {
-                self.add_memory(0);
-                self.analysis.imports.insert(idx);
-            },
-            External::Global(_) => {},
+    /// Adds every item that the part of the module visited by `f` refers
+    /// to.
+    fn add_refs<F>(&mut self, f: F) -> Result<(), Error>
+    where
+        F: FnOnce(&mut Refs) -> Result<(), reencode::Error<Error>>,
+    {
+        let mut refs = Refs::default();
+        f(&mut refs)?;
+        self.analysis.ref_functions.extend(refs.ref_functions);
+        for item in refs.items {
+            match item {
+                Item::Function(i) => self.add_function(i)?,
+                Item::Table(i) => self.add_table(i)?,
+                Item::Memory(i) => self.add_memory(i)?,
+                Item::Global(i) => self.add_global(i)?,
+                Item::Tag(i) => self.add_tag(i)?,
+                Item::Type(i) => self.add_type(i)?,
+                Item::DataSegment(i) => self.add_data_segment(i)?,
+                Item::ElementSegment(i) => self.add_element_segment(i)?,
+                _ => {}
+            }
         }
+        Ok(())
+    }
+}
+
+fn kind_name(kind: ImportKind) -> &'static str {
+    match kind {
+        ImportKind::Function => "function",
+        ImportKind::Table => "table",
+        ImportKind::Memory => "memory",
+        ImportKind::Global => "global",
+        ImportKind::Tag => "tag",
     }
+}
 
-    fn add_data_segment(&mut self, data: &DataSegment) {
-        self.add_memory(data.index());
-        self.add_init_expr(data.offset());
+/// Returns the value of the offset expression `expr` of an active segment,
+/// or `None` if it isn't a constant, e.g. a `global.get`.
+fn const_offset(expr: &wasmparser::ConstExpr) -> Option<u64> {
+    let mut reader = expr.get_operators_reader();
+    let offset = match reader.read().ok()? {
+        Operator::I32Const { value } => u64::from(value as u32),
+        Operator::I64Const { value } => value as u64,
+        _ => return None,
+    };
+    match reader.read().ok()? {
+        Operator::End => Some(offset),
+        _ => None,
     }
+}
+
+/// Returns the error for `idx` of the index space `kind` pointing past the
+/// end of its section, which has `len` entries.
+///
+/// An empty section is reported as missing, like an absent one.
+fn out_of_range(kind: &'static str, len: usize, idx: u32) -> Error {
+    if len == 0 {
+        Error::MissingSection(kind)
+    } else {
+        Error::IndexOutOfRange { kind, index: idx }
+    }
+}
+
+/// Collects the indices that a part of a module refers to.
+///
+/// This re-encodes the part and throws the result away, which visits every
+/// index of every instruction without listing them all here.
+#[derive(Default)]
+struct Refs {
+    items: Vec<Item>,
+    /// The functions taken with `ref.func`, which have to be declared.
+    ref_functions: Vec<u32>,
+}
+
+impl Reencode for Refs {
+    type Error = Error;
 
-    fn add_element_segment(&mut self, seg: &ElementSegment) {
-        for member in seg.members() {
-            self.add_function(*member);
+    fn instruction<'a>(&mut self, op: Operator<'a>)
+        -> Result<wasm_encoder::Instruction<'a>, reencode::Error<Error>>
+    {
+        if let Operator::RefFunc { function_index } = op {
+            self.ref_functions.push(function_index);
         }
-        self.add_table(seg.index());
-        self.add_init_expr(seg.offset());
+        reencode::utils::instruction(self, op)
+    }
+
+    fn function_index(&mut self, i: u32) -> Result<u32, reencode::Error<Error>> {
+        self.items.push(Item::Function(i));
+        Ok(i)
+    }
+
+    fn table_index(&mut self, i: u32) -> Result<u32, reencode::Error<Error>> {
+        self.items.push(Item::Table(i));
+        Ok(i)
+    }
+
+    fn memory_index(&mut self, i: u32) -> Result<u32, reencode::Error<Error>> {
+        self.items.push(Item::Memory(i));
+        Ok(i)
+    }
+
+    fn global_index(&mut self, i: u32) -> Result<u32, reencode::Error<Error>> {
+        self.items.push(Item::Global(i));
+        Ok(i)
+    }
+
+    fn type_index(&mut self, i: u32) -> Result<u32, reencode::Error<Error>> {
+        self.items.push(Item::Type(i));
+        Ok(i)
+    }
+
+    fn tag_index(&mut self, i: u32) -> Result<u32, reencode::Error<Error>> {
+        self.items.push(Item::Tag(i));
+        Ok(i)
+    }
+
+    fn data_index(&mut self, i: u32) -> Result<u32, reencode::Error<Error>> {
+        self.items.push(Item::DataSegment(i));
+        Ok(i)
+    }
+
+    fn element_index(&mut self, i: u32) -> Result<u32, reencode::Error<Error>> {
+        self.items.push(Item::ElementSegment(i));
+        Ok(i)
     }
 }
 
 struct RemapContext<'a> {
+    module: &'a Module<'a>,
     analysis: &'a Analysis,
     config: &'a Config,
-    functions: Vec<u32>,
-    globals: Vec<u32>,
-    types: Vec<u32>,
-    tables: Vec<u32>,
-    memories: Vec<u32>,
+    map: &'a Remapping,
+    /// Where the code of each function ended up, only recorded if
+    /// `track_code` is set because the module has DWARF or a source map to
+    /// rewrite.
+    code_map: CodeMap,
+    track_code: bool,
 }
 
 impl<'a> RemapContext<'a> {
-    fn new(m: &Module, analysis: &'a Analysis, config: &'a Config) -> RemapContext<'a> {
-        let mut nfunctions = 0;
-        let mut functions = Vec::new();
-        let mut nglobals = 0;
-        let mut globals = Vec::new();
-        let mut types = Vec::new();
-        let mut ntables = 0;
-        let mut tables = Vec::new();
-        let mut nmemories = 0;
-        let mut memories = Vec::new();
-
-        if let Some(s) = m.type_section() {
-            let mut removed = 0;
-            for i in 0..(s.types().len() as u32) {
-                if analysis.types.contains(&i) {
-                    types.push(i - removed);
-                } else {
-                    debug!("gc type {}", i);
-                    types.push(u32::max_value());
-                    removed += 1;
-                }
-            }
+    fn new(module: &'a Module<'a>,
+           analysis: &'a Analysis,
+           config: &'a Config,
+           source_map: bool)
+        -> RemapContext<'a>
+    {
+        RemapContext {
+            module,
+            analysis,
+            config,
+            map: &analysis.remapping,
+            code_map: CodeMap::default(),
+            track_code: source_map ||
+                module.customs.iter().any(|s| dwarf::is_debug_section(s.name())),
         }
-        if let Some(s) = m.import_section() {
-            for (i, import) in s.entries().iter().enumerate() {
-                let (dst, ndst) = match *import.external() {
-                    External::Function(_) => (&mut functions, &mut nfunctions),
-                    External::Table(_) => (&mut tables, &mut ntables),
-                    External::Memory(_) => (&mut memories, &mut nmemories),
-                    External::Global(_) => (&mut globals, &mut nglobals),
-                };
-                if analysis.imports.contains(&(i as u32)) {
-                    dst.push(*ndst);
-                    *ndst += 1;
+    }
+
+    fn remap_type_section(&mut self, s: wasmparser::TypeSectionReader)
+        -> Result<wasm_encoder::TypeSection, Error>
+    {
+        let mut section = wasm_encoder::TypeSection::new();
+        let mut idx = 0;
+        for group in s {
+            let group = group?;
+            let explicit = group.is_explicit_rec_group();
+            let mut types = Vec::new();
+            for ty in group.into_types() {
+                if self.analysis.types.contains(&idx) {
+                    types.push(self.sub_type(ty)?);
                 } else {
-                    debug!("gc import {}", i);
-                    dst.push(u32::max_value());
+                    debug!("removing type {}", idx);
                 }
+                idx += 1;
             }
-        }
-        if let Some(s) = m.function_section() {
-            for i in 0..(s.entries().len() as u32) {
-                if analysis.codes.contains(&i) {
-                    functions.push(nfunctions);
-                    nfunctions += 1;
-                } else {
-                    debug!("gc function {}", i);
-                    functions.push(u32::max_value());
+            if explicit && !types.is_empty() {
+                section.ty().rec(types);
+            } else {
+                for ty in types {
+                    section.ty().subtype(&ty);
                 }
             }
         }
-        if let Some(s) = m.global_section() {
-            for i in 0..(s.entries().len() as u32) {
-                if analysis.globals.contains(&i) {
-                    globals.push(nglobals);
-                    nglobals += 1;
-                } else {
-                    debug!("gc global {}", i);
-                    globals.push(u32::max_value());
-                }
+        Ok(section)
+    }
+
+    fn remap_import_section(&mut self) -> Result<wasm_encoder::ImportSection, Error> {
+        let mut section = wasm_encoder::ImportSection::new();
+        let module = self.module;
+        for (i, import) in module.imports.iter().enumerate() {
+            if !self.analysis.imports.contains(&(i as u32)) {
+                debug!("removing import {}", i);
+                continue
             }
+            debug!("remap import entry {:?}", import);
+            self.parse_import(&mut section, *import)?;
         }
-        if let Some(s) = m.table_section() {
-            for i in 0..(s.entries().len() as u32) {
-                if analysis.tables.contains(&i) {
-                    tables.push(ntables);
-                    ntables += 1;
-                } else {
-                    debug!("gc table {}", i);
-                    tables.push(u32::max_value());
-                }
+        Ok(section)
+    }
+
+    fn remap_function_section(&mut self) -> Result<wasm_encoder::FunctionSection, Error> {
+        let mut section = wasm_encoder::FunctionSection::new();
+        let module = self.module;
+        for (i, &ty) in module.functions.iter().enumerate() {
+            if !self.analysis.codes.contains(&(i as u32)) {
+                debug!("removing function {}", i);
+                continue
             }
+            section.function(self.type_index(ty)?);
         }
-        if let Some(s) = m.memory_section() {
-            for i in 0..(s.entries().len() as u32) {
-                if analysis.memories.contains(&i) {
-                    memories.push(nmemories);
-                    nmemories += 1;
-                } else {
-                    debug!("gc memory {}", i);
-                    memories.push(u32::max_value());
-                }
+        Ok(section)
+    }
+
+    fn remap_table_section(&mut self) -> Result<wasm_encoder::TableSection, Error> {
+        let mut section = wasm_encoder::TableSection::new();
+        let module = self.module;
+        for (i, table) in module.tables.iter().enumerate() {
+            if !self.analysis.tables.contains(&(i as u32)) {
+                debug!("removing table {}", i);
+                continue
             }
+            self.parse_table(&mut section, table.clone())?;
         }
+        Ok(section)
+    }
 
-        RemapContext {
-            analysis,
-            functions,
-            globals,
-            memories,
-            tables,
-            types,
-            config,
+    fn remap_memory_section(&mut self) -> Result<wasm_encoder::MemorySection, Error> {
+        let mut section = wasm_encoder::MemorySection::new();
+        let module = self.module;
+        for (i, &memory) in module.memories.iter().enumerate() {
+            if !self.analysis.memories.contains(&(i as u32)) {
+                debug!("removing memory {}", i);
+                continue
+            }
+            section.memory(self.memory_type(memory)?);
         }
+        Ok(section)
     }
 
-    fn retain<T>(&self, set: &BTreeSet<u32>, list: &mut Vec<T>, name: &str) {
-        for i in (0..list.len()).rev().map(|x| x as u32) {
-            if !set.contains(&i) {
-                debug!("removing {} {}", name, i);
-                list.remove(i as usize);
+    fn remap_tag_section(&mut self) -> Result<wasm_encoder::TagSection, Error> {
+        let mut section = wasm_encoder::TagSection::new();
+        let module = self.module;
+        for (i, &tag) in module.tags.iter().enumerate() {
+            if !self.analysis.tags.contains(&(i as u32)) {
+                debug!("removing tag {}", i);
+                continue
             }
+            section.tag(self.tag_type(tag)?);
         }
+        Ok(section)
     }
 
-    fn remap_type_section(&self, s: &mut TypeSection) -> bool {
-        self.retain(&self.analysis.types, s.types_mut(), "type");
-        for t in s.types_mut() {
-            self.remap_type(t);
+    fn remap_global_section(&mut self) -> Result<wasm_encoder::GlobalSection, Error> {
+        let mut section = wasm_encoder::GlobalSection::new();
+        let module = self.module;
+        for (i, global) in module.globals.iter().enumerate() {
+            if !self.analysis.globals.contains(&(i as u32)) {
+                debug!("removing global {}", i);
+                continue
+            }
+            self.parse_global(&mut section, global.clone())?;
         }
-        s.types().len() > 0
+        Ok(section)
     }
 
-    fn remap_type(&self, t: &mut Type) {
-        match *t {
-            Type::Function(ref mut t) => self.remap_function_type(t),
+    fn remap_export_section(&mut self) -> Result<wasm_encoder::ExportSection, Error> {
+        let mut section = wasm_encoder::ExportSection::new();
+        let module = self.module;
+        for (i, export) in module.exports.iter().enumerate() {
+            if !self.analysis.exports.contains(&(i as u32)) {
+                debug!("removing export {}", i);
+                continue
+            }
+            self.parse_export(&mut section, *export)?;
         }
+        Ok(section)
     }
 
-    fn remap_function_type(&self, t: &mut FunctionType) {
-        for param in t.params_mut() {
-            self.remap_value_type(param);
+    fn remap_element_section(&mut self) -> Result<wasm_encoder::ElementSection, Error> {
+        let mut section = wasm_encoder::ElementSection::new();
+        let module = self.module;
+        for (i, seg) in module.elements.iter().enumerate() {
+            if !self.analysis.element_segments.contains(&(i as u32)) {
+                debug!("removing element segment {}", i);
+                continue
+            }
+            self.parse_element(&mut section, seg.clone())?;
         }
-        if let Some(m) = t.return_type_mut().as_mut() {
-            self.remap_value_type(m);
+        // Live code may take a function with `ref.func` that only a removed
+        // segment, global or export declared, so it's declared again.
+        let declared = self.declared_functions()?;
+        let mut undeclared = Vec::new();
+        for &i in self.analysis.ref_functions.difference(&declared) {
+            undeclared.push(self.function_index(i)?);
         }
-    }
-
-    fn remap_value_type(&self, t: &mut ValueType) {
-        drop(t);
-    }
-
-    fn remap_import_section(&self, s: &mut ImportSection) -> bool {
-        self.retain(&self.analysis.imports, s.entries_mut(), "import");
-        for i in s.entries_mut() {
-            self.remap_import_entry(i);
+        if !undeclared.is_empty() {
+            section.declared(wasm_encoder::Elements::Functions(undeclared.into()));
         }
-        s.entries().len() > 0
+        Ok(section)
     }
 
-    fn remap_import_entry(&self, s: &mut ImportEntry) {
-        debug!("remap import entry {:?}", s);
-        match *s.external_mut() {
-            External::Function(ref mut f) => self.remap_type_idx(f),
-            External::Table(_) => {}
-            External::Memory(_) => {}
-            External::Global(_) => {}
+    /// Returns the functions that the kept exports, globals, tables and
+    /// element segments refer to, which declares them for `ref.func`.
+    fn declared_functions(&self) -> Result<BTreeSet<u32>, Error> {
+        let module = self.module;
+        let analysis = self.analysis;
+        let mut refs = Refs::default();
+        for (i, export) in module.exports.iter().enumerate() {
+            if !analysis.exports.contains(&(i as u32)) {
+                continue
+            }
+            if let ExternalKind::Func | ExternalKind::FuncExact = export.kind {
+                refs.items.push(Item::Function(export.index));
+            }
         }
-    }
-
-    fn remap_function_section(&self, s: &mut FunctionSection) -> bool {
-        self.retain(&self.analysis.codes, s.entries_mut(), "function");
-        for f in s.entries_mut() {
-            self.remap_func(f);
+        for (i, global) in module.globals.iter().enumerate() {
+            if analysis.globals.contains(&(i as u32)) {
+                refs.parse_global(&mut wasm_encoder::GlobalSection::new(), global.clone())?;
+            }
         }
-        s.entries().len() > 0
-    }
-
-    fn remap_func(&self, f: &mut Func) {
-        self.remap_type_idx(f.type_ref_mut());
-    }
-
-    fn remap_table_section(&self, s: &mut TableSection) -> bool {
-        self.retain(&self.analysis.tables, s.entries_mut(), "table");
-        for t in s.entries_mut() {
-            drop(t); // TODO
+        for (i, table) in module.tables.iter().enumerate() {
+            if analysis.tables.contains(&(i as u32)) {
+                refs.parse_table(&mut wasm_encoder::TableSection::new(), table.clone())?;
+            }
+        }
+        for (i, seg) in module.elements.iter().enumerate() {
+            if analysis.element_segments.contains(&(i as u32)) {
+                refs.parse_element(&mut wasm_encoder::ElementSection::new(), seg.clone())?;
+            }
         }
-        s.entries().len() > 0
+        Ok(refs.items
+            .into_iter()
+            .filter_map(|item| match item {
+                Item::Function(i) => Some(i),
+                _ => None,
+            })
+            .collect())
     }
 
-    fn remap_memory_section(&self, s: &mut MemorySection) -> bool {
-        self.retain(&self.analysis.memories, s.entries_mut(), "memory");
-        for m in s.entries_mut() {
-            drop(m); // TODO
+    fn remap_data_section(&mut self) -> Result<wasm_encoder::DataSection, Error> {
+        let mut section = wasm_encoder::DataSection::new();
+        let module = self.module;
+        for (i, data) in module.data_segments.iter().enumerate() {
+            if !self.analysis.data_segments.contains(&(i as u32)) {
+                debug!("removing data segment {}", i);
+                continue
+            }
+            self.parse_data(&mut section, data.clone())?;
         }
-        s.entries().len() > 0
+        Ok(section)
     }
 
-    fn remap_global_section(&self, s: &mut GlobalSection) -> bool {
-        self.retain(&self.analysis.globals, s.entries_mut(), "global");
-        for g in s.entries_mut() {
-            self.remap_global_entry(g);
+    fn remap_code_section(&mut self) -> Result<wasm_encoder::CodeSection, Error> {
+        let mut section = wasm_encoder::CodeSection::new();
+        let module = self.module;
+        let count = self.analysis.codes.len();
+        for (i, body) in module.code.iter().enumerate() {
+            if !self.analysis.codes.contains(&(i as u32)) {
+                continue
+            }
+            if !self.track_code {
+                self.parse_function_body(&mut section, body.clone())?;
+                continue
+            }
+
+            // Encode the body one instruction at a time to record where
+            // each instruction moves, as their encoding may change size.
+            let mut func = self.new_function_with_parsed_locals(body)?;
+            let mut reader = body.get_operators_reader()?;
+            let mut instructions = Vec::new();
+            let range = body.range();
+            while !reader.eof() {
+                instructions.push((reader.original_position() - range.start, func.byte_len()));
+                let instruction = self.parse_instruction(&mut reader)?;
+                func.instruction(&instruction);
+            }
+            let start = leb128_len(count) + section.byte_len() + leb128_len(func.byte_len());
+            let old = range.start - module.code_offset..range.end - module.code_offset;
+            self.code_map.add_function(old, start..start + func.byte_len(), instructions);
+            section.function(&func);
         }
-        s.entries().len() > 0
+        Ok(section)
     }
 
-    fn remap_global_entry(&self, s: &mut GlobalEntry) {
-        self.remap_global_type(s.global_type_mut());
-        self.remap_init_expr(s.init_expr_mut());
+    fn remap_name_section(&mut self, s: &wasmparser::CustomSectionReader)
+        -> Result<wasm_encoder::NameSection, Error>
+    {
+        let data = wasmparser::BinaryReader::new(s.data(), s.data_offset());
+        let reader = wasmparser::NameSectionReader::new(data);
+        self.rebuild_name_section(reader).map_err(malformed_name_section)
     }
 
-    fn remap_global_type(&self, s: &mut GlobalType) {
-        drop(s);
-    }
+    fn rebuild_name_section(&mut self, reader: wasmparser::NameSectionReader)
+        -> Result<wasm_encoder::NameSection, Error>
+    {
+        let map = self.map;
+        let mut res = wasm_encoder::NameSection::new();
+        for subsection in reader {
+            match subsection? {
+                // module name, we leave this unmangled
+                Name::Module { name, .. } => res.module(name),
 
-    fn remap_init_expr(&self, s: &mut InitExpr) {
-        for code in s.code_mut() {
-            self.remap_opcode(code);
-        }
-    }
+                Name::Function(names) => {
+                    res.functions(&self.remap_name_map(names, &map.functions)?);
+                }
+                Name::Type(names) => res.types(&self.remap_name_map(names, &map.types)?),
+                Name::Table(names) => res.tables(&self.remap_name_map(names, &map.tables)?),
+                Name::Memory(names) => {
+                    res.memories(&self.remap_name_map(names, &map.memories)?);
+                }
+                Name::Global(names) => res.globals(&self.remap_name_map(names, &map.globals)?),
+                Name::Element(names) => {
+                    res.elements(&self.remap_name_map(names, &map.element_segments)?);
+                }
+                Name::Data(names) => res.data(&self.remap_name_map(names, &map.data_segments)?),
+                Name::Tag(names) => res.tags(&self.remap_name_map(names, &map.tags)?),
 
-    fn remap_export_section(&self, s: &mut ExportSection) -> bool {
-        self.retain(&self.analysis.exports, s.entries_mut(), "export");
-        for s in s.entries_mut() {
-            self.remap_export_entry(s);
-        }
-        s.entries().len() > 0
-    }
+                // locals and labels are numbered per function and fields per
+                // type, only the function or type is renumbered
+                Name::Local(names) => {
+                    res.locals(&self.remap_indirect_name_map(names, &map.functions)?);
+                }
+                Name::Label(names) => {
+                    res.labels(&self.remap_indirect_name_map(names, &map.functions)?);
+                }
+                Name::Field(names) => {
+                    res.fields(&self.remap_indirect_name_map(names, &map.types)?);
+                }
 
-    fn remap_export_entry(&self, s: &mut ExportEntry) {
-        match *s.internal_mut() {
-            Internal::Function(ref mut i) => self.remap_function_idx(i),
-            Internal::Table(ref mut i) => self.remap_table_idx(i),
-            Internal::Memory(ref mut i) => self.remap_memory_idx(i),
-            Internal::Global(ref mut i) => self.remap_global_idx(i),
+                Name::Unknown { ty, data, .. } => {
+                    info!("passing through unknown name subsection: {}", ty);
+                    res.raw(ty, data);
+                }
+            }
         }
+        Ok(res)
     }
 
-    fn remap_element_section(&self, s: &mut ElementSection) -> bool {
-        for s in s.entries_mut() {
-            self.remap_element_segment(s);
+    /// Renumbers the items named in `names` by `indices`, dropping the
+    /// names of removed items.
+    fn remap_name_map(&self, names: wasmparser::NameMap, indices: &[Option<u32>])
+        -> Result<wasm_encoder::NameMap, Error>
+    {
+        let mut res = wasm_encoder::NameMap::new();
+        for naming in names {
+            let naming = naming?;
+            if let Some(&Some(index)) = indices.get(naming.index as usize) {
+                res.append(index, &self.demangle(naming.name));
+            }
         }
-        true
+        Ok(res)
     }
 
-    fn remap_element_segment(&self, s: &mut ElementSegment) {
-        let mut i = s.index();
-        self.remap_table_idx(&mut i);
-        assert_eq!(s.index(), i);
-        for m in s.members_mut() {
-            self.remap_function_idx(m);
+    fn remap_indirect_name_map(&self,
+                               names: wasmparser::IndirectNameMap,
+                               indices: &[Option<u32>])
+        -> Result<wasm_encoder::IndirectNameMap, Error>
+    {
+        let mut res = wasm_encoder::IndirectNameMap::new();
+        for naming in names {
+            let naming = naming?;
+            let index = match indices.get(naming.index as usize) {
+                Some(&Some(index)) => index,
+                _ => continue,
+            };
+            let mut inner = wasm_encoder::NameMap::new();
+            for name in naming.names {
+                let name = name?;
+                inner.append(name.index, &self.demangle(name.name));
+            }
+            res.append(index, &inner);
         }
-        self.remap_init_expr(s.offset_mut());
+        Ok(res)
     }
 
-    fn remap_code_section(&self, s: &mut CodeSection) -> bool {
-        self.retain(&self.analysis.codes, s.bodies_mut(), "code");
-        for s in s.bodies_mut() {
-            self.remap_func_body(s);
+    fn demangle(&self, name: &str) -> String {
+        if self.config.demangle {
+            format!("{}", rustc_demangle::demangle(name))
+        } else {
+            name.to_string()
         }
-        s.bodies().len() > 0
     }
+}
 
-    fn remap_func_body(&self, b: &mut FuncBody) {
-        self.remap_code(b.code_mut());
-    }
+impl<'a> Reencode for RemapContext<'a> {
+    type Error = Error;
 
-    fn remap_code(&self, c: &mut Opcodes) {
-        for op in c.elements_mut() {
-            self.remap_opcode(op);
-        }
+    fn function_index(&mut self, i: u32) -> Result<u32, reencode::Error<Error>> {
+        remap_idx(&self.map.functions, "function", i)
     }
 
-    fn remap_opcode(&self, op: &mut Opcode) {
-        match *op {
-            Opcode::Block(ref mut b) |
-            Opcode::Loop(ref mut b) |
-            Opcode::If(ref mut b) => self.remap_block_type(b),
-            Opcode::Call(ref mut f) => self.remap_function_idx(f),
-            Opcode::CallIndirect(ref mut t, _) => self.remap_type_idx(t),
-            Opcode::GetGlobal(ref mut i) |
-            Opcode::SetGlobal(ref mut i) => self.remap_global_idx(i),
-            _ => {}
-        }
+    fn table_index(&mut self, i: u32) -> Result<u32, reencode::Error<Error>> {
+        remap_idx(&self.map.tables, "table", i)
     }
 
-    fn remap_block_type(&self, bt: &mut BlockType) {
-        match *bt {
-            BlockType::Value(ref mut v) => self.remap_value_type(v),
-            BlockType::NoResult => {}
-        }
+    fn memory_index(&mut self, i: u32) -> Result<u32, reencode::Error<Error>> {
+        remap_idx(&self.map.memories, "memory", i)
     }
 
-    fn remap_data_section(&self, s: &mut DataSection) -> bool {
-        for data in s.entries_mut() {
-            self.remap_data_segment(data);
-        }
-        true
+    fn global_index(&mut self, i: u32) -> Result<u32, reencode::Error<Error>> {
+        trace!("global {} => {:?}", i, self.map.globals.get(i as usize));
+        remap_idx(&self.map.globals, "global", i)
     }
 
-    fn remap_data_segment(&self, segment: &mut DataSegment) {
-        let mut i = segment.index();
-        self.remap_memory_idx(&mut i);
-        assert_eq!(segment.index(), i);
-        self.remap_init_expr(segment.offset_mut());
+    fn type_index(&mut self, i: u32) -> Result<u32, reencode::Error<Error>> {
+        remap_idx(&self.map.types, "type", i)
     }
 
-    fn remap_type_idx(&self, i: &mut u32) {
-        *i = self.types[*i as usize];
-        assert!(*i != u32::max_value());
+    fn tag_index(&mut self, i: u32) -> Result<u32, reencode::Error<Error>> {
+        remap_idx(&self.map.tags, "tag", i)
     }
 
-    fn remap_function_idx(&self, i: &mut u32) {
-        *i = self.functions[*i as usize];
-        assert!(*i != u32::max_value());
+    fn data_index(&mut self, i: u32) -> Result<u32, reencode::Error<Error>> {
+        remap_idx(&self.map.data_segments, "data segment", i)
     }
 
-    fn remap_global_idx(&self, i: &mut u32) {
-        trace!("global {} => {}", *i, self.globals[*i as usize]);
-        *i = self.globals[*i as usize];
-        assert!(*i != u32::max_value());
+    fn element_index(&mut self, i: u32) -> Result<u32, reencode::Error<Error>> {
+        remap_idx(&self.map.element_segments, "element segment", i)
     }
 
-    fn remap_table_idx(&self, i: &mut u32) {
-        *i = self.tables[*i as usize];
-        assert!(*i != u32::max_value());
+    fn data_count(&mut self, _count: u32) -> Result<u32, reencode::Error<Error>> {
+        Ok(self.analysis.data_segments.len() as u32)
     }
+}
 
-    fn remap_memory_idx(&self, i: &mut u32) {
-        *i = self.memories[*i as usize];
-        assert!(*i != u32::max_value());
+/// Returns the size of the LEB128 encoding of `n`.
+pub fn leb128_len(n: usize) -> usize {
+    let mut len = 1;
+    let mut n = n >> 7;
+    while n != 0 {
+        len += 1;
+        n >>= 7;
     }
+    len
+}
 
-    fn remap_name_section(&self, s: &mut CustomSection) {
-        let data = s.payload_mut();
-        *data = self.rebuild_name_section(data)
-            .expect("malformed name section");
+fn remap_idx(map: &[Option<u32>], kind: &'static str, i: u32)
+    -> Result<u32, reencode::Error<Error>>
+{
+    match map.get(i as usize) {
+        Some(&Some(new)) => Ok(new),
+        Some(&None) => {
+            Err(reencode::Error::UserError(Error::DanglingReference { kind, index: i }))
+        }
+        None => {
+            Err(reencode::Error::UserError(Error::IndexOutOfRange { kind, index: i }))
+        }
     }
+}
 
-    fn rebuild_name_section(&self, mut data: &[u8]) -> Result<Vec<u8>, Error> {
-        // if true { return Ok(data.to_vec()) }
-        let mut res = Vec::new();
-        while data.len() > 0 {
-            let name_type = u8::from(VarUint7::deserialize(&mut data)?);
-            let name_payload_len = u32::from(VarUint32::deserialize(&mut data)?);
-            let (mut bytes, rest) = data.split_at(name_payload_len as usize);
-            data = rest;
-
-            match name_type {
-                // module name, we leave this unmangled
-                0 => {
-                    VarUint7::from(name_type).serialize(&mut res)?;
-                    VarUint32::from(name_payload_len).serialize(&mut res)?;
-                    res.extend(bytes);
-                }
-
-                // function map
-                1 => {
-                    let mut map = self.decode_name_map(&mut bytes)?;
-                    map.retain(|m| self.functions[m.0 as usize] != u32::max_value());
-                    for slot in map.iter_mut() {
-                        self.remap_function_idx(&mut slot.0);
-                    }
-                    let mut tmp = Vec::new();
-                    self.serialize_name_map(&map, &mut tmp);
-
-                    VarUint7::from(name_type).serialize(&mut res)?;
-                    VarUint32::from(tmp.len()).serialize(&mut res)?;
-                    res.extend(tmp);
-                }
-
-                // local names
-                2 => {
-                    let count = u32::from(VarUint32::deserialize(&mut bytes)?);
-                    let mut locals = Vec::new();
-                    for _ in 0..count {
-                        let index = u32::from(VarUint32::deserialize(&mut bytes)?);
-                        let map = self.decode_name_map(&mut bytes)?;
-                        let new_index = self.functions[index as usize];
-                        if new_index == u32::max_value() {
-                            continue
-                        }
-                        locals.push((new_index, map));
-                    }
-
-                    let mut tmp = Vec::new();
-                    VarUint32::from(locals.len()).serialize(&mut tmp).unwrap();
-                    for (index, map) in locals {
-                        VarUint32::from(index).serialize(&mut tmp).unwrap();
-                        self.serialize_name_map(&map, &mut tmp);
-                    }
-
-                    VarUint7::from(name_type).serialize(&mut res)?;
-                    VarUint32::from(tmp.len()).serialize(&mut res)?;
-                    res.extend(tmp);
-                }
+fn malformed_name_section(e: Error) -> Error {
+    match e {
+        Error::Parse(e) => Error::MalformedNameSection(e.message().to_string()),
+        e => e,
+    }
+}
 
-                n => panic!("unknown name subsection type: {}", n),
-            }
-        }
-        Ok(res)
+/// Returns the names that the module's `name` section gives to its
+/// functions, globals, tables, memories, tags, types and segments.
+pub fn item_names<'a>(module: &Module<'a>) -> Result<BTreeMap<Item, &'a str>, Error> {
+    match module.custom_section("name") {
+        Some(s) => decode_item_names(s).map_err(malformed_name_section),
+        None => Ok(BTreeMap::new()),
     }
+}
 
-    fn decode_name_map<'b>(&self, bytes: &mut &'b [u8])
-        -> Result<Vec<(u32, &'b str)>, Error>
-    {
-        let count = u32::from(VarUint32::deserialize(bytes)?);
-        let mut names = Vec::with_capacity(count as usize);
-        for _ in 0..count {
-            let index = u32::from(VarUint32::deserialize(bytes)?);
-            let name_len = u32::from(VarUint32::deserialize(bytes)?);
-            let (name, rest) = bytes.split_at(name_len as usize);
-            *bytes = rest;
-            let name = str::from_utf8(name)
-                .expect("ill-formed utf-8 in name subsection");
-            names.push((index, name));
-        }
-        Ok(names)
-    }
-
-    fn serialize_name_map(&self, names: &[(u32, &str)], dst: &mut Vec<u8>) {
-        VarUint32::from(names.len()).serialize(dst).unwrap();
-        for &(index, name) in names {
-            VarUint32::from(index).serialize(dst).unwrap();
-            let name = if self.config.demangle {
-                format!("{}", rustc_demangle::demangle(name))
-            } else {
-                name.to_string()
-            };
-            VarUint32::from(name.len()).serialize(dst).unwrap();
-            dst.extend(name.as_bytes());
+fn decode_item_names<'a>(s: &wasmparser::CustomSectionReader<'a>)
+    -> Result<BTreeMap<Item, &'a str>, Error>
+{
+    let data = wasmparser::BinaryReader::new(s.data(), s.data_offset());
+    let mut names = BTreeMap::new();
+    for subsection in wasmparser::NameSectionReader::new(data) {
+        let (map, item): (_, fn(u32) -> Item) = match subsection? {
+            Name::Function(map) => (map, Item::Function),
+            Name::Global(map) => (map, Item::Global),
+            Name::Table(map) => (map, Item::Table),
+            Name::Memory(map) => (map, Item::Memory),
+            Name::Tag(map) => (map, Item::Tag),
+            Name::Type(map) => (map, Item::Type),
+            Name::Data(map) => (map, Item::DataSegment),
+            Name::Element(map) => (map, Item::ElementSegment),
+            _ => continue,
+        };
+        for naming in map {
+            let naming = naming?;
+            names.insert(item(naming.index), naming.name);
         }
     }
+    Ok(names)
 }
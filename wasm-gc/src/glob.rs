@@ -0,0 +1,82 @@
+/// Returns whether `name` matches the glob `pattern`.
+///
+/// A `*` in the pattern matches any (possibly empty) sequence of characters
+/// and a `?` matches exactly one character; everything else must match
+/// literally.
+pub fn matches(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let name = name.chars().collect::<Vec<_>>();
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some(&'*') => {
+                backtrack = Some((p, n));
+                p += 1;
+                continue
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+                continue
+            }
+            _ => {}
+        }
+        match backtrack {
+            Some((bp, bn)) => {
+                p = bp + 1;
+                n = bn + 1;
+                backtrack = Some((bp, bn + 1));
+            }
+            None => return false,
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::matches;
+
+    #[test]
+    fn literal() {
+        assert!(matches("main", "main"));
+        assert!(!matches("main", "mai"));
+        assert!(!matches("main", "mains"));
+        assert!(!matches("main", ""));
+    }
+
+    #[test]
+    fn star() {
+        assert!(matches("*", ""));
+        assert!(matches("*", "anything"));
+        assert!(matches("__*", "__"));
+        assert!(matches("__*", "__tls_base"));
+        assert!(matches("*_base", "__tls_base"));
+        assert!(!matches("__*", "_tls"));
+        assert!(matches("a**b", "ab"));
+    }
+
+    #[test]
+    fn question_mark() {
+        assert!(matches("__udivsi?", "__udivsi3"));
+        assert!(!matches("__udivsi?", "__udivsi"));
+        assert!(!matches("?", ""));
+        assert!(matches("?", "é"));
+    }
+
+    #[test]
+    fn backtracking() {
+        assert!(matches("*ab", "aab"));
+        assert!(matches("a*b*c", "abxbyc"));
+        assert!(matches("*a?c", "abacabc"));
+        assert!(!matches("a*b*c", "abxbyd"));
+        assert!(!matches("*ab", "aba"));
+    }
+
+    #[test]
+    fn empty_pattern() {
+        assert!(matches("", ""));
+        assert!(!matches("", "a"));
+    }
+}
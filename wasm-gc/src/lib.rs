@@ -1,29 +1,65 @@
-extern crate parity_wasm;
 #[macro_use]
 extern crate log;
+extern crate gimli;
 extern crate rustc_demangle;
+extern crate serde_json;
+extern crate wasm_encoder;
+extern crate wasmparser;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
 
+mod analysis;
+mod blacklist;
+mod dwarf;
+mod features;
 mod gc;
+mod glob;
 mod error;
+mod module;
+mod report;
+mod source_map;
 
+use std::collections::{BTreeSet, HashSet};
+use std::fs;
 use std::path::Path;
-use parity_wasm::elements::{
-    Module,
-    Serialize,
-    Deserialize
-};
+use module::Module;
 
+pub use analysis::{Analysis, Item, Remapping};
+pub use blacklist::{COMPILER_RT_BLACKLIST, THREAD_RUNTIME_EXPORTS};
 pub use error::Error;
+pub use report::{GcReport, ReportItem};
 
 pub struct Config {
     demangle: bool,
+    blacklist: HashSet<String>,
+    keep_exports: HashSet<String>,
+    keep_functions: BTreeSet<u32>,
+    keep_patterns: Vec<String>,
+    remove_exports: Vec<String>,
+    export_allowlist: Option<Vec<String>>,
+    reject_unlisted_exports: bool,
+    record_processed_by: bool,
+    reject_undeclared_features: bool,
 }
 
 impl Config {
     /// Creates a blank slate of configuration, ready to gc wasm files.
+    ///
+    /// The blacklist of dropped exports starts out as
+    /// `COMPILER_RT_BLACKLIST`.
     pub fn new() -> Config {
         Config {
             demangle: true,
+            blacklist: COMPILER_RT_BLACKLIST.iter().map(|s| s.to_string()).collect(),
+            keep_exports: HashSet::new(),
+            keep_functions: BTreeSet::new(),
+            keep_patterns: Vec::new(),
+            remove_exports: Vec::new(),
+            export_allowlist: None,
+            reject_unlisted_exports: false,
+            record_processed_by: true,
+            reject_undeclared_features: false,
         }
     }
 
@@ -34,18 +70,251 @@ impl Config {
         self
     }
 
+    /// Adds `name` to the blacklist of exports.
+    ///
+    /// Exports whose name is on the blacklist are removed from the module and
+    /// are not considered roots, so anything only they reference is gc'd.
+    pub fn blacklist(&mut self, name: &str) -> &mut Self {
+        self.blacklist.insert(name.to_string());
+        self
+    }
+
+    /// Removes `name` from the blacklist of exports, keeping any export with
+    /// that name.
+    pub fn unblacklist(&mut self, name: &str) -> &mut Self {
+        self.blacklist.remove(name);
+        self
+    }
+
+    /// Replaces the entire blacklist of exports with `names`.
+    ///
+    /// Passing `COMPILER_RT_BLACKLIST` restores the default.
+    pub fn set_blacklist<I, S>(&mut self, names: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.blacklist = names.into_iter().map(|s| s.into()).collect();
+        self
+    }
+
+    /// Empties the blacklist so that no export is dropped.
+    pub fn clear_blacklist(&mut self) -> &mut Self {
+        self.blacklist.clear();
+        self
+    }
+
+    /// Keeps the export named `name`, even if it is blacklisted or matches
+    /// `remove_export`.
+    ///
+    /// An `export_allowlist` takes priority over this: an export it doesn't
+    /// list is removed even if it's kept here.
+    pub fn keep_export(&mut self, name: &str) -> &mut Self {
+        self.keep_exports.insert(name.to_string());
+        self
+    }
+
+    /// Treats the function at index `idx` as a root so that it, and
+    /// everything it references, survives the gc pass.
+    ///
+    /// The index is in the function index space of the input module, so
+    /// imported functions come first.
+    pub fn keep_function_index(&mut self, idx: u32) -> &mut Self {
+        self.keep_functions.insert(idx);
+        self
+    }
+
+    /// Keeps everything matching the glob `pattern` alive.
+    ///
+    /// The pattern is matched against export names, the field names of
+    /// imported functions and the function names in the `name` section
+    /// (both mangled and demangled). A `*` matches any sequence of characters
+    /// and a `?` matches any single character.
+    pub fn keep(&mut self, pattern: &str) -> &mut Self {
+        self.keep_patterns.push(pattern.to_string());
+        self
+    }
+
+    /// Removes every export whose name matches the glob `pattern`.
+    ///
+    /// Removed exports are not roots, so anything only reachable from them
+    /// is gc'd as well. Exports kept through `keep_export` or `keep` are
+    /// never removed, but exports listed by `export_allowlist` are, so the
+    /// allowlist can be narrowed down.
+    pub fn remove_export(&mut self, pattern: &str) -> &mut Self {
+        self.remove_exports.push(pattern.to_string());
+        self
+    }
+
+    /// Restricts the module's exports to those matching one of the glob
+    /// `patterns`.
+    ///
+    /// Every other export is removed, and listed exports are kept even if
+    /// they're blacklisted, though not if they match `remove_export`. See
+    /// `reject_unlisted_exports` to fail instead of removing them.
+    ///
+    /// Modules with a shared memory keep their `THREAD_RUNTIME_EXPORTS` even
+    /// if they aren't listed, unless they're removed through `blacklist` or
+    /// `remove_export`.
+    pub fn export_allowlist<I, S>(&mut self, patterns: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.export_allowlist = Some(patterns.into_iter().map(|s| s.into()).collect());
+        self
+    }
+
+    /// Configures whether an export not on the `export_allowlist` causes the
+    /// gc pass to fail with an error rather than being removed.
+    ///
+    /// This has no effect unless an allowlist is configured.
+    pub fn reject_unlisted_exports(&mut self, reject: bool) -> &mut Self {
+        self.reject_unlisted_exports = reject;
+        self
+    }
+
+    /// Configures whether wasm-gc records itself in the `processed-by` field
+    /// of the module's `producers` section, adding the section if the module
+    /// doesn't have one.
+    ///
+    /// This is enabled by default.
+    pub fn record_processed_by(&mut self, record: bool) -> &mut Self {
+        self.record_processed_by = record;
+        self
+    }
+
+    /// Configures whether using a proposal that isn't declared in the
+    /// module's `target_features` section causes the gc pass to fail with an
+    /// error rather than a logged warning.
+    ///
+    /// Only the output is checked, so a feature used by removed code is fine
+    /// and `analyze` doesn't check anything. Modules without a
+    /// `target_features` section aren't checked, and one that can't be
+    /// decoded is an error only if this is enabled. The section itself is
+    /// kept as is, even if the code using a feature is removed.
+    pub fn reject_undeclared_features(&mut self, reject: bool) -> &mut Self {
+        self.reject_undeclared_features = reject;
+        self
+    }
+
+    fn lists_export(&self, name: &str) -> bool {
+        match self.export_allowlist {
+            Some(ref list) => list.iter().any(|p| glob::matches(p, name)),
+            None => true,
+        }
+    }
+
+    /// Returns whether the allowlist lets a module keep the export `name`,
+    /// where `threaded` is whether the module has a shared memory.
+    fn allows_export(&self, name: &str, threaded: bool) -> bool {
+        self.lists_export(name) || (threaded && THREAD_RUNTIME_EXPORTS.contains(&name))
+    }
+
+    fn drops_export(&self, name: &str, threaded: bool) -> bool {
+        if !self.allows_export(name, threaded) {
+            return true
+        }
+        if self.keeps_export(name) {
+            return false
+        }
+        if self.remove_exports.iter().any(|p| glob::matches(p, name)) {
+            return true
+        }
+        // Listing an export on the allowlist only overrides the blacklist.
+        let listed = self.export_allowlist.is_some() && self.lists_export(name);
+        self.blacklist.contains(name) && !listed
+    }
+
+    fn keeps_export(&self, name: &str) -> bool {
+        self.keep_exports.contains(name) || self.keeps_name(name)
+    }
+
+    fn keeps_name(&self, name: &str) -> bool {
+        self.keep_patterns.iter().any(|p| glob::matches(p, name))
+    }
+
     /// Runs gc passes over the wasm input module `input`, returning the
     /// serialized output.
-    pub fn gc(&mut self, mut bytecode: &[u8]) -> Result<Vec<u8>, Error> {
-        let mut module = Module::deserialize(&mut bytecode).map_err(error::from)?;
-        self._gc(&mut module);
-        let mut output = Vec::new();
-        module.serialize(&mut output).map_err(error::from)?;
-        Ok(output)
+    ///
+    /// Malformed or unsupported modules are reported through `Error` rather
+    /// than by panicking.
+    pub fn gc(&mut self, bytecode: &[u8]) -> Result<Vec<u8>, Error> {
+        let module = Module::parse(bytecode)?;
+        self._gc(&module)
+    }
+
+    /// Runs the liveness analysis of the gc pass over the wasm input module
+    /// `input` without rewriting it.
+    ///
+    /// The returned `Analysis` reports what a gc pass would remove and can
+    /// explain why an item is kept through `Analysis::why_live`.
+    pub fn analyze(&mut self, bytecode: &[u8]) -> Result<Analysis, Error> {
+        let input_size = bytecode.len();
+        let module = Module::parse(bytecode)?;
+        self._analyze(&module, input_size)
+    }
+
+    /// Like `gc`, but also returns a report of every item that was kept or
+    /// removed.
+    pub fn gc_with_report(&mut self, bytecode: &[u8])
+        -> Result<(Vec<u8>, GcReport), Error>
+    {
+        let (output, analysis) = self.gc_with_analysis(bytecode)?;
+        Ok((output, analysis.report))
+    }
+
+    /// Like `gc`, but also returns the analysis the module was rewritten
+    /// with.
+    ///
+    /// `Analysis::remapping` maps indices of the input module to indices of
+    /// the output module, e.g. to translate stack traces of the output back
+    /// to the input. The analysis's report has `output_size` filled in.
+    pub fn gc_with_analysis(&mut self, bytecode: &[u8])
+        -> Result<(Vec<u8>, Analysis), Error>
+    {
+        let input_size = bytecode.len();
+        let module = Module::parse(bytecode)?;
+        let mut analysis = self._analyze(&module, input_size)?;
+        let output = gc::remap(self, &module, &analysis)?;
+        analysis.report.output_size = Some(output.len());
+        Ok((output, analysis))
+    }
+
+    /// Like `gc_with_analysis`, but also rewrites the source map `map` of
+    /// the input module, the contents of its `.wasm.map` file, for the
+    /// output.
+    ///
+    /// The mappings are moved to where the code ended up and those of
+    /// removed functions are dropped. The map is passed with each call
+    /// rather than stored like other options, so that a later gc of another
+    /// module doesn't rewrite it again.
+    pub fn source_map(&mut self, bytecode: &[u8], map: &str)
+        -> Result<(Vec<u8>, Analysis, String), Error>
+    {
+        let input_size = bytecode.len();
+        let module = Module::parse(bytecode)?;
+        let mut analysis = self._analyze(&module, input_size)?;
+        let (output, map) = gc::remap_with_source_map(self, &module, &analysis, map)?;
+        analysis.report.output_size = Some(output.len());
+        Ok((output, analysis, map))
+    }
+
+    fn _analyze(&self, module: &Module, input_size: usize) -> Result<Analysis, Error> {
+        let mut analysis = gc::analyze(self, module)?;
+        analysis.report = report::build(self, module, &analysis)?;
+        analysis.report.input_size = input_size;
+        Ok(analysis)
+    }
+
+    fn _gc(&self, module: &Module) -> Result<Vec<u8>, Error> {
+        gc::run(self, module)
     }
+}
 
-    fn _gc(&mut self, module: &mut Module) {
-        gc::run(self, module);
+impl Default for Config {
+    fn default() -> Config {
+        Config::new()
     }
 }
 
@@ -59,9 +328,9 @@ where
 }
 
 fn _gc_file(input: &Path, output: &Path) -> Result<(), Error> {
-    let mut module = parity_wasm::deserialize_file(input).map_err(error::from)?;
-    Config::new()._gc(&mut module);
-    parity_wasm::serialize_to_file(output, module).map_err(error::from)?;
+    let contents = fs::read(input)?;
+    let result = Config::new().gc(&contents)?;
+    fs::write(output, result)?;
 
     Ok(())
 }
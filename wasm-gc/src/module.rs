@@ -0,0 +1,224 @@
+use std::ops::Range;
+
+use wasmparser::{CustomSectionReader, Data, Element, Encoding, Export, FunctionBody};
+use wasmparser::{Global, Import, MemoryType, Parser, Payload, SubType, Table, TagType};
+use wasmparser::TypeRef;
+
+use error::Error;
+
+/// A parsed wasm module, borrowing from its encoded form.
+///
+/// Every section is read up front so the gc pass can look items up by index.
+/// Rewriting the module parses `data` again and re-encodes it section by
+/// section.
+pub struct Module<'a> {
+    pub data: &'a [u8],
+    /// Every type of the type section, with recursion groups flattened.
+    pub types: Vec<SubType>,
+    /// The range of type indices of each recursion group, including the
+    /// implicit group of a type declared on its own.
+    pub rec_groups: Vec<Range<u32>>,
+    /// The index in `rec_groups` of the recursion group of each type.
+    pub type_rec_groups: Vec<u32>,
+    pub imports: Vec<Import<'a>>,
+    /// The position in `imports` of each import, by the index space it
+    /// belongs to, indexed by `ImportKind`.
+    pub import_positions: [Vec<u32>; 5],
+    /// The type index of each function of the function section.
+    pub functions: Vec<u32>,
+    pub tables: Vec<Table<'a>>,
+    pub memories: Vec<MemoryType>,
+    pub tags: Vec<TagType>,
+    pub globals: Vec<Global<'a>>,
+    pub exports: Vec<Export<'a>>,
+    pub start: Option<u32>,
+    pub elements: Vec<Element<'a>>,
+    pub data_count: Option<u32>,
+    /// The offset of the contents of the code section in `data`, which is
+    /// what DWARF code addresses are relative to.
+    pub code_offset: usize,
+    pub code: Vec<FunctionBody<'a>>,
+    pub data_segments: Vec<Data<'a>>,
+    pub customs: Vec<CustomSectionReader<'a>>,
+}
+
+impl<'a> Module<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Module<'a>, Error> {
+        let mut module = Module {
+            data,
+            types: Vec::new(),
+            rec_groups: Vec::new(),
+            type_rec_groups: Vec::new(),
+            imports: Vec::new(),
+            import_positions: Default::default(),
+            functions: Vec::new(),
+            tables: Vec::new(),
+            memories: Vec::new(),
+            tags: Vec::new(),
+            globals: Vec::new(),
+            exports: Vec::new(),
+            start: None,
+            elements: Vec::new(),
+            data_count: None,
+            code_offset: 0,
+            code: Vec::new(),
+            data_segments: Vec::new(),
+            customs: Vec::new(),
+        };
+        for payload in Parser::new(0).parse_all(data) {
+            let payload = match payload {
+                Ok(payload) => payload,
+                // The parser only finds functions without a body at the end
+                // of the module, this is a reference into an absent section
+                // like any other.
+                Err(ref e) if e.offset() == data.len() &&
+                    !module.functions.is_empty() && module.code.is_empty() => {
+                    return Err(Error::MissingSection("code"))
+                }
+                Err(e) => return Err(e.into()),
+            };
+            match payload {
+                Payload::Version { encoding: Encoding::Module, .. } => {}
+                Payload::Version { .. } => {
+                    return Err(Error::Unsupported("components".to_string()))
+                }
+                Payload::TypeSection(s) => {
+                    for group in s {
+                        let start = module.types.len() as u32;
+                        module.types.extend(group?.into_types());
+                        let end = module.types.len() as u32;
+                        let group = module.rec_groups.len() as u32;
+                        module.rec_groups.push(start..end);
+                        module.type_rec_groups.extend((start..end).map(|_| group));
+                    }
+                }
+                Payload::ImportSection(s) => {
+                    for import in s {
+                        let import = import?;
+                        let position = module.imports.len() as u32;
+                        module.import_positions[ImportKind::of(&import.ty) as usize]
+                            .push(position);
+                        module.imports.push(import);
+                    }
+                }
+                Payload::FunctionSection(s) => {
+                    for ty in s {
+                        module.functions.push(ty?);
+                    }
+                }
+                Payload::TableSection(s) => {
+                    for table in s {
+                        module.tables.push(table?);
+                    }
+                }
+                Payload::MemorySection(s) => {
+                    for memory in s {
+                        module.memories.push(memory?);
+                    }
+                }
+                Payload::TagSection(s) => {
+                    for tag in s {
+                        module.tags.push(tag?);
+                    }
+                }
+                Payload::GlobalSection(s) => {
+                    for global in s {
+                        module.globals.push(global?);
+                    }
+                }
+                Payload::ExportSection(s) => {
+                    for export in s {
+                        module.exports.push(export?);
+                    }
+                }
+                Payload::StartSection { func, .. } => module.start = Some(func),
+                Payload::ElementSection(s) => {
+                    for element in s {
+                        module.elements.push(element?);
+                    }
+                }
+                Payload::DataCountSection { count, .. } => {
+                    module.data_count = Some(count);
+                }
+                Payload::DataSection(s) => {
+                    for data in s {
+                        module.data_segments.push(data?);
+                    }
+                }
+                Payload::CodeSectionStart { range, .. } => {
+                    module.code_offset = range.start;
+                }
+                Payload::CodeSectionEntry(body) => module.code.push(body),
+                Payload::CustomSection(s) => module.customs.push(s),
+                Payload::End(_) => {}
+                other => {
+                    let msg = match other.as_section() {
+                        Some((id, _)) => format!("section {}", id),
+                        None => "unknown payload".to_string(),
+                    };
+                    return Err(Error::Unsupported(msg))
+                }
+            }
+        }
+        Ok(module)
+    }
+
+    /// Returns the custom section called `name`, if there is one.
+    pub fn custom_section(&self, name: &str) -> Option<&CustomSectionReader<'a>> {
+        self.customs.iter().find(|s| s.name() == name)
+    }
+
+    /// Returns the type indices of the recursion group that type `idx` is
+    /// part of.
+    pub fn rec_group(&self, idx: u32) -> Option<Range<u32>> {
+        self.type_rec_groups
+            .get(idx as usize)
+            .map(|&group| self.rec_groups[group as usize].clone())
+    }
+
+    /// Returns the import that `idx` of the index space `kind` refers to,
+    /// along with its position in the import section.
+    pub fn import(&self, kind: ImportKind, idx: u32) -> Option<(u32, &Import<'a>)> {
+        self.import_positions[kind as usize]
+            .get(idx as usize)
+            .map(|&i| (i, &self.imports[i as usize]))
+    }
+
+    /// Returns whether the module imports or defines a shared memory, i.e.
+    /// whether it's meant to be run on several threads.
+    pub fn has_shared_memory(&self) -> bool {
+        let imported = self.imports.iter().any(|import| match import.ty {
+            TypeRef::Memory(ty) => ty.shared,
+            _ => false,
+        });
+        imported || self.memories.iter().any(|ty| ty.shared)
+    }
+
+    /// Returns the number of imports of the given kind, which is where the
+    /// defined items of that kind start in their index space.
+    pub fn num_imports(&self, kind: ImportKind) -> u32 {
+        self.import_positions[kind as usize].len() as u32
+    }
+}
+
+/// The index space that an import belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportKind {
+    Function,
+    Table,
+    Memory,
+    Global,
+    Tag,
+}
+
+impl ImportKind {
+    pub fn of(ty: &TypeRef) -> ImportKind {
+        match *ty {
+            TypeRef::Func(_) | TypeRef::FuncExact(_) => ImportKind::Function,
+            TypeRef::Table(_) => ImportKind::Table,
+            TypeRef::Memory(_) => ImportKind::Memory,
+            TypeRef::Global(_) => ImportKind::Global,
+            TypeRef::Tag(_) => ImportKind::Tag,
+        }
+    }
+}
@@ -0,0 +1,256 @@
+use rustc_demangle;
+use wasmparser::{FromReader, Parser, Payload, SectionLimited};
+use error::Error;
+use analysis::{Analysis, Item};
+use gc;
+use module::{ImportKind, Module};
+use Config;
+
+/// A summary of everything a gc pass kept and removed.
+///
+/// Every item of the input module is listed in its category along with
+/// whether it was kept.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct GcReport {
+    /// The size of the input module in bytes.
+    pub input_size: usize,
+    /// The size of the output module in bytes, or `None` if the module
+    /// was only analyzed and not rewritten.
+    pub output_size: Option<usize>,
+    /// Functions defined in the module; imported functions are listed under
+    /// `imports`.
+    pub functions: Vec<ReportItem>,
+    /// Entries of the import section.
+    pub imports: Vec<ReportItem>,
+    /// Entries of the export section.
+    pub exports: Vec<ReportItem>,
+    /// Globals defined in the module.
+    pub globals: Vec<ReportItem>,
+    /// Entries of the type section.
+    pub types: Vec<ReportItem>,
+    /// Tables defined in the module.
+    pub tables: Vec<ReportItem>,
+    /// Memories defined in the module.
+    pub memories: Vec<ReportItem>,
+    /// Tags defined in the module.
+    pub tags: Vec<ReportItem>,
+    /// Entries of the data section.
+    pub data_segments: Vec<ReportItem>,
+    /// Entries of the element section.
+    pub element_segments: Vec<ReportItem>,
+}
+
+/// A single item of the input module in a `GcReport`.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct ReportItem {
+    /// The item's index in the input module.
+    ///
+    /// Functions, globals, tables, memories and tags use their index space, so
+    /// imported items come first. Imports, exports, types and segments use
+    /// their position in their section.
+    pub index: u32,
+    /// The item's name, if known.
+    ///
+    /// Imports are named by `module.field`, exports by their field and
+    /// everything else by the `name` section.
+    pub name: Option<String>,
+    /// The encoded size of the item in bytes.
+    pub size: usize,
+    /// Whether the item survived the gc pass.
+    pub kept: bool,
+}
+
+impl GcReport {
+    /// Returns the number of bytes occupied by removed items.
+    ///
+    /// This doesn't account for section headers or the `name` section, so it
+    /// is a close estimate of the savings rather than the exact difference
+    /// between `input_size` and `output_size`.
+    pub fn removed_size(&self) -> usize {
+        self.categories()
+            .iter()
+            .flat_map(|&(_, items)| items.iter())
+            .filter(|item| !item.kept)
+            .map(|item| item.size)
+            .sum()
+    }
+
+    /// Returns each category of items along with a singular name for it,
+    /// such as `"function"`.
+    pub fn categories(&self) -> [(&'static str, &[ReportItem]); 10] {
+        [
+            ("function", &self.functions),
+            ("import", &self.imports),
+            ("export", &self.exports),
+            ("global", &self.globals),
+            ("type", &self.types),
+            ("table", &self.tables),
+            ("memory", &self.memories),
+            ("tag", &self.tags),
+            ("data segment", &self.data_segments),
+            ("element segment", &self.element_segments),
+        ]
+    }
+}
+
+/// Builds a report of what `analysis` keeps and removes from `module`.
+///
+/// The size fields are left for the caller to fill in.
+pub fn build(config: &Config, module: &Module, analysis: &Analysis)
+    -> Result<GcReport, Error>
+{
+    let mut report = GcReport::default();
+    let imported_functions = module.num_imports(ImportKind::Function);
+    let imported_globals = module.num_imports(ImportKind::Global);
+    let imported_tables = module.num_imports(ImportKind::Table);
+    let imported_memories = module.num_imports(ImportKind::Memory);
+    let imported_tags = module.num_imports(ImportKind::Tag);
+    let names = gc::item_names(module)?;
+    let name = |item| {
+        names.get(&item).map(|name| {
+            if config.demangle {
+                rustc_demangle::demangle(name).to_string()
+            } else {
+                name.to_string()
+            }
+        })
+    };
+
+    for payload in Parser::new(0).parse_all(module.data) {
+        match payload? {
+            Payload::ImportSection(s) => {
+                let sizes = item_sizes(s)?;
+                for (i, (entry, size)) in module.imports.iter().zip(sizes).enumerate() {
+                    report.imports.push(ReportItem {
+                        index: i as u32,
+                        name: Some(format!("{}.{}", entry.module, entry.name)),
+                        size,
+                        kept: analysis.imports.contains(&(i as u32)),
+                    });
+                }
+            }
+            Payload::ExportSection(s) => {
+                let sizes = item_sizes(s)?;
+                for (i, (entry, size)) in module.exports.iter().zip(sizes).enumerate() {
+                    report.exports.push(ReportItem {
+                        index: i as u32,
+                        name: Some(entry.name.to_string()),
+                        size,
+                        kept: analysis.exports.contains(&(i as u32)),
+                    });
+                }
+            }
+            Payload::TypeSection(s) => {
+                let end = s.range().end;
+                let mut offsets = Vec::new();
+                for group in s {
+                    offsets.extend(group?.into_types_and_offsets().map(|(o, _)| o));
+                }
+                for (i, size) in sizes_from_offsets(&offsets, end).into_iter().enumerate() {
+                    report.types.push(ReportItem {
+                        index: i as u32,
+                        name: name(Item::Type(i as u32)),
+                        size,
+                        kept: analysis.types.contains(&(i as u32)),
+                    });
+                }
+            }
+            Payload::GlobalSection(s) => {
+                for (i, size) in item_sizes(s)?.into_iter().enumerate() {
+                    report.globals.push(ReportItem {
+                        index: imported_globals + i as u32,
+                        name: name(Item::Global(imported_globals + i as u32)),
+                        size,
+                        kept: analysis.globals.contains(&(i as u32)),
+                    });
+                }
+            }
+            Payload::TableSection(s) => {
+                for (i, size) in item_sizes(s)?.into_iter().enumerate() {
+                    report.tables.push(ReportItem {
+                        index: imported_tables + i as u32,
+                        name: name(Item::Table(imported_tables + i as u32)),
+                        size,
+                        kept: analysis.tables.contains(&(i as u32)),
+                    });
+                }
+            }
+            Payload::MemorySection(s) => {
+                for (i, size) in item_sizes(s)?.into_iter().enumerate() {
+                    report.memories.push(ReportItem {
+                        index: imported_memories + i as u32,
+                        name: name(Item::Memory(imported_memories + i as u32)),
+                        size,
+                        kept: analysis.memories.contains(&(i as u32)),
+                    });
+                }
+            }
+            Payload::TagSection(s) => {
+                for (i, size) in item_sizes(s)?.into_iter().enumerate() {
+                    report.tags.push(ReportItem {
+                        index: imported_tags + i as u32,
+                        name: name(Item::Tag(imported_tags + i as u32)),
+                        size,
+                        kept: analysis.tags.contains(&(i as u32)),
+                    });
+                }
+            }
+            Payload::DataSection(s) => {
+                for (i, size) in item_sizes(s)?.into_iter().enumerate() {
+                    report.data_segments.push(ReportItem {
+                        index: i as u32,
+                        name: name(Item::DataSegment(i as u32)),
+                        size,
+                        kept: analysis.data_segments.contains(&(i as u32)),
+                    });
+                }
+            }
+            Payload::ElementSection(s) => {
+                for (i, size) in item_sizes(s)?.into_iter().enumerate() {
+                    report.element_segments.push(ReportItem {
+                        index: i as u32,
+                        name: name(Item::ElementSegment(i as u32)),
+                        size,
+                        kept: analysis.element_segments.contains(&(i as u32)),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    for (i, body) in module.code.iter().enumerate() {
+        let index = imported_functions + i as u32;
+        // Removing a function removes the length prefix of its body too.
+        let len = body.range().len();
+        report.functions.push(ReportItem {
+            index,
+            name: name(Item::Function(index)),
+            size: gc::leb128_len(len) + len,
+            kept: analysis.codes.contains(&(i as u32)),
+        });
+    }
+    Ok(report)
+}
+
+/// Returns the encoded size of each item of `section`.
+fn item_sizes<'a, T>(section: SectionLimited<'a, T>) -> Result<Vec<usize>, Error>
+where
+    T: FromReader<'a>,
+{
+    let end = section.range().end;
+    let mut offsets = Vec::new();
+    for item in section.into_iter_with_offsets() {
+        offsets.push(item?.0);
+    }
+    Ok(sizes_from_offsets(&offsets, end))
+}
+
+fn sizes_from_offsets(offsets: &[usize], end: usize) -> Vec<usize> {
+    offsets.iter()
+        .enumerate()
+        .map(|(i, &offset)| offsets.get(i + 1).cloned().unwrap_or(end) - offset)
+        .collect()
+}
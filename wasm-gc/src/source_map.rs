@@ -0,0 +1,233 @@
+use serde_json::{self, Value};
+use dwarf::CodeMap;
+use error::Error;
+
+const BASE64: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Rewrites the source map `map` of the input for the new code layout.
+///
+/// Source maps of wasm modules use the offset of an instruction in the
+/// module as its generated column. The contents of the code section started
+/// at `old_offset` in the input and start at `new_offset` in the output.
+/// Mappings of removed code are dropped, everything but the `mappings` of
+/// the map is kept as is.
+pub fn rewrite(map: &str, code: &CodeMap, old_offset: usize, new_offset: usize)
+    -> Result<String, Error>
+{
+    let mut json: Value = serde_json::from_str(map)
+        .map_err(|e| Error::MalformedSourceMap(e.to_string()))?;
+    let mappings = {
+        let mappings = json.get("mappings")
+            .and_then(|m| m.as_str())
+            .ok_or_else(|| malformed("missing `mappings`"))?;
+        rewrite_mappings(mappings, |column| {
+            let address = column.checked_sub(old_offset as u64)?;
+            code.map(address).map(|address| address + new_offset as u64)
+        })?
+    };
+    json["mappings"] = Value::String(mappings);
+    serde_json::to_string(&json).map_err(|e| Error::MalformedSourceMap(e.to_string()))
+}
+
+/// Rewrites the generated column of every segment of `mappings` with `map`,
+/// dropping the segments it returns `None` for.
+fn rewrite_mappings<F>(mappings: &str, mut map: F) -> Result<String, Error>
+where
+    F: FnMut(u64) -> Option<u64>,
+{
+    let mut out = String::new();
+    // The fields of a segment are relative to the previous segment, so
+    // track their absolute values in the input and in what was written.
+    let mut old = [0i64; 5];
+    let mut new = [0i64; 5];
+    for (i, line) in mappings.split(';').enumerate() {
+        if i > 0 {
+            out.push(';');
+        }
+        old[0] = 0;
+        new[0] = 0;
+        let mut last_column = None;
+        for segment in line.split(',').filter(|s| !s.is_empty()) {
+            let fields = decode_segment(segment)?;
+            for (old, field) in old.iter_mut().zip(&fields) {
+                *old = old.checked_add(*field)
+                    .ok_or_else(|| malformed("value in `mappings` is out of range"))?;
+            }
+            if old[0] < 0 {
+                return Err(malformed("negative generated column"))
+            }
+            // The source, line, column and name are checked even if the
+            // segment is dropped, later segments are relative to them.
+            if old[1..].iter().any(|&value| value < 0) {
+                return Err(malformed("negative value in `mappings`"))
+            }
+            let column = match map(old[0] as u64) {
+                Some(column) => column as i64,
+                None => continue,
+            };
+            // Code whose encoding shrank may map onto the same offset as the
+            // previous segment, only the first mapping of an offset is kept.
+            if last_column == Some(column) {
+                continue
+            }
+            if last_column.is_some() {
+                out.push(',');
+            }
+            last_column = Some(column);
+            let values = [column, old[1], old[2], old[3], old[4]];
+            for (new, &value) in new.iter_mut().zip(&values).take(fields.len()) {
+                let delta = value.checked_sub(*new)
+                    .ok_or_else(|| malformed("value in `mappings` is out of range"))?;
+                encode_vlq(&mut out, delta);
+                *new = value;
+            }
+        }
+    }
+    Ok(out)
+}
+
+fn decode_segment(segment: &str) -> Result<Vec<i64>, Error> {
+    let mut fields = Vec::new();
+    let mut value = 0u64;
+    let mut shift = 0;
+    for c in segment.bytes() {
+        let digit = BASE64.iter()
+            .position(|&b| b == c)
+            .ok_or_else(|| malformed("invalid base64 in `mappings`"))? as u64;
+        // Only the low four bits of a digit at shift 60 still fit.
+        let bits = digit & 0x1f;
+        if shift > 60 || (shift == 60 && bits > 0xf) {
+            return Err(malformed("value in `mappings` is too large"))
+        }
+        value |= bits << shift;
+        shift += 5;
+        if digit & 0x20 != 0 {
+            continue
+        }
+        let magnitude = (value >> 1) as i64;
+        fields.push(if value & 1 != 0 { -magnitude } else { magnitude });
+        value = 0;
+        shift = 0;
+    }
+    if shift != 0 {
+        return Err(malformed("truncated segment in `mappings`"))
+    }
+    match fields.len() {
+        1 | 4 | 5 => Ok(fields),
+        n => Err(Error::MalformedSourceMap(format!("segment with {} fields", n))),
+    }
+}
+
+fn encode_vlq(out: &mut String, value: i64) {
+    let mut value = if value < 0 {
+        (value.unsigned_abs() << 1) | 1
+    } else {
+        (value as u64) << 1
+    };
+    loop {
+        let mut digit = value & 0x1f;
+        value >>= 5;
+        if value != 0 {
+            digit |= 0x20;
+        }
+        out.push(BASE64[digit as usize] as char);
+        if value == 0 {
+            break
+        }
+    }
+}
+
+fn malformed(msg: &str) -> Error {
+    Error::MalformedSourceMap(msg.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_segment, encode_vlq, rewrite_mappings};
+
+    /// Encodes the segments of a single line of mappings given by their
+    /// absolute fields.
+    fn encode(segments: &[[i64; 4]]) -> String {
+        let mut out = String::new();
+        let mut last = [0; 4];
+        for (i, segment) in segments.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            for (last, &value) in last.iter_mut().zip(segment) {
+                encode_vlq(&mut out, value - *last);
+                *last = value;
+            }
+        }
+        out
+    }
+
+    fn decode(mappings: &str) -> Vec<[i64; 4]> {
+        let mut last = [0; 4];
+        mappings.split(',')
+            .map(|segment| {
+                let fields = decode_segment(segment).unwrap();
+                for (last, field) in last.iter_mut().zip(fields) {
+                    *last += field;
+                }
+                last
+            })
+            .collect()
+    }
+
+    #[test]
+    fn vlq_round_trips() {
+        for &value in &[0, 1, -1, 15, 16, -16, 1000, -1000, 1 << 40] {
+            let mut out = String::new();
+            encode_vlq(&mut out, value);
+            assert_eq!(decode_segment(&out).unwrap(), [value]);
+        }
+    }
+
+    #[test]
+    fn drops_mappings_of_removed_functions() {
+        let mappings = encode(&[
+            [10, 0, 1, 0],
+            [15, 0, 2, 4],
+            [25, 0, 3, 0],
+            [35, 1, 4, 2],
+        ]);
+        // The function at 20..30 was removed, the one after it moved down.
+        let rewritten = rewrite_mappings(&mappings, |column| match column {
+            20..=29 => None,
+            c if c >= 30 => Some(c - 10),
+            c => Some(c),
+        }).unwrap();
+        assert_eq!(decode(&rewritten), [
+            [10, 0, 1, 0],
+            [15, 0, 2, 4],
+            [25, 1, 4, 2],
+        ]);
+    }
+
+    #[test]
+    fn keeps_the_first_mapping_of_a_column() {
+        let mappings = encode(&[[10, 0, 1, 0], [11, 0, 2, 0], [12, 0, 3, 0]]);
+        let rewritten = rewrite_mappings(&mappings, |column| Some(column / 2)).unwrap();
+        assert_eq!(decode(&rewritten), [[5, 0, 1, 0], [6, 0, 3, 0]]);
+    }
+
+    #[test]
+    fn rejects_malformed_mappings() {
+        let keep = |column| Some(column);
+        assert!(rewrite_mappings("A!AA", keep).is_err());
+        assert!(rewrite_mappings("AAAg", keep).is_err());
+        assert!(rewrite_mappings("AA", keep).is_err());
+        assert!(rewrite_mappings("D", keep).is_err());
+        // The top bit of the thirteenth digit would be shifted out.
+        assert!(rewrite_mappings("ggggggggggggQ", keep).is_err());
+        // Both segments are valid on their own, but the sum of their
+        // columns doesn't fit.
+        assert!(rewrite_mappings("ggggggggggggI", keep).is_ok());
+        assert!(rewrite_mappings("ggggggggggggI,ggggggggggggI", keep).is_err());
+        // A source index of -(2^63 - 1) in a segment that's dropped, then one
+        // more down to i64::MIN, whose delta can't be negated.
+        let drop_first = |column| if column == 0 { None } else { Some(column) };
+        assert!(rewrite_mappings("A////////////PAA,CDAA", drop_first).is_err());
+    }
+}
@@ -0,0 +1,69 @@
+extern crate wasm_gc;
+extern crate wasmparser;
+extern crate wat;
+
+mod common;
+
+use common::{data, gc, kept};
+use wasmparser::{Parser, Payload};
+
+/// Returns the count of the data count section of `wasm`, if it has one.
+fn data_count(wasm: &[u8]) -> Option<u32> {
+    Parser::new(0).parse_all(wasm)
+        .filter_map(|payload| match payload.unwrap() {
+            Payload::DataCountSection { count, .. } => Some(count),
+            _ => None,
+        })
+        .next()
+}
+
+#[test]
+fn bulk_memory_operators() {
+    let output = gc(r#"
+        (module
+            (memory 1)
+            (data (i32.const 0) "active")
+            (data "unused")
+            (data "used")
+            (func (export "init")
+                i32.const 16
+                i32.const 0
+                i32.const 4
+                memory.init 2
+                data.drop 2
+                i32.const 0
+                i32.const 16
+                i32.const 4
+                memory.copy
+                i32.const 0
+                i32.const 0
+                i32.const 4
+                memory.fill)
+            (func $only_user
+                i32.const 0
+                i32.const 0
+                i32.const 6
+                memory.init 1))
+    "#);
+    assert_eq!(data(&output), [b"active".to_vec(), b"used".to_vec()]);
+    assert_eq!(data_count(&output), Some(2));
+    assert!(kept(&output, "func").is_empty());
+}
+
+#[test]
+fn data_count_of_removed_segments() {
+    let output = gc(r#"
+        (module
+            (memory 1)
+            (data "unused")
+            (func (export "drop") (param i32)
+                local.get 0
+                i32.const 0
+                i32.const 1
+                memory.fill)
+            (func $dead
+                data.drop 0))
+    "#);
+    assert!(data(&output).is_empty());
+    assert_eq!(data_count(&output), Some(0));
+}
@@ -0,0 +1,256 @@
+#![allow(dead_code)]
+
+use wasm_gc::Config;
+use wasmparser::{BinaryReader, ElementItems, Name, NameSectionReader, Operator, Parser};
+use wasmparser::{Payload, Validator, WasmFeatures};
+
+/// Garbage collects the module `wat` with the default configuration and
+/// returns the output after checking that both validate.
+pub fn gc(wat: &str) -> Vec<u8> {
+    gc_with(&mut Config::new(), wat)
+}
+
+/// Like `gc`, with the configuration `config`.
+pub fn gc_with(config: &mut Config, wat: &str) -> Vec<u8> {
+    let input = wat::parse_str(wat).unwrap();
+    validate(&input).expect("invalid input");
+    let output = config.gc(&input).unwrap();
+    validate(&output).expect("invalid output");
+    output
+}
+
+pub fn validate(wasm: &[u8]) -> Result<(), wasmparser::BinaryReaderError> {
+    Validator::new_with_features(WasmFeatures::all()).validate_all(wasm).map(|_| ())
+}
+
+/// Returns the names that the `name` section of `wasm` gives to the items
+/// of `kind`, one of `func`, `global`, `table`, `memory`, `tag`, `type`,
+/// `data` or `elem`, by their index.
+pub fn names(wasm: &[u8], kind: &str) -> Vec<(u32, String)> {
+    let mut res = Vec::new();
+    for payload in Parser::new(0).parse_all(wasm) {
+        let s = match payload.unwrap() {
+            Payload::CustomSection(ref s) if s.name() == "name" => s.clone(),
+            _ => continue,
+        };
+        let data = BinaryReader::new(s.data(), s.data_offset());
+        for subsection in NameSectionReader::new(data) {
+            let map = match (subsection.unwrap(), kind) {
+                (Name::Function(map), "func") |
+                (Name::Global(map), "global") |
+                (Name::Table(map), "table") |
+                (Name::Memory(map), "memory") |
+                (Name::Tag(map), "tag") |
+                (Name::Type(map), "type") |
+                (Name::Data(map), "data") |
+                (Name::Element(map), "elem") => map,
+                _ => continue,
+            };
+            for naming in map {
+                let naming = naming.unwrap();
+                res.push((naming.index, naming.name.to_string()));
+            }
+        }
+    }
+    res
+}
+
+/// Returns the names that the `name` section of `wasm` gives to the items
+/// of `kind`, one of `local`, `label` or `field`, by the index of their
+/// function or type and then by their own index.
+pub fn indirect_names(wasm: &[u8], kind: &str) -> Vec<(u32, Vec<(u32, String)>)> {
+    let mut res = Vec::new();
+    for payload in Parser::new(0).parse_all(wasm) {
+        let s = match payload.unwrap() {
+            Payload::CustomSection(ref s) if s.name() == "name" => s.clone(),
+            _ => continue,
+        };
+        let data = BinaryReader::new(s.data(), s.data_offset());
+        for subsection in NameSectionReader::new(data) {
+            let map = match (subsection.unwrap(), kind) {
+                (Name::Local(map), "local") |
+                (Name::Label(map), "label") |
+                (Name::Field(map), "field") => map,
+                _ => continue,
+            };
+            for naming in map {
+                let naming = naming.unwrap();
+                let names = naming.names.into_iter()
+                    .map(|name| {
+                        let name = name.unwrap();
+                        (name.index, name.name.to_string())
+                    })
+                    .collect();
+                res.push((naming.index, names));
+            }
+        }
+    }
+    res
+}
+
+/// Returns the names of the exports of `wasm`, in order.
+pub fn exports(wasm: &[u8]) -> Vec<String> {
+    let mut res = Vec::new();
+    for payload in Parser::new(0).parse_all(wasm) {
+        if let Payload::ExportSection(s) = payload.unwrap() {
+            for export in s {
+                res.push(export.unwrap().name.to_string());
+            }
+        }
+    }
+    res
+}
+
+/// Returns the imports of `wasm` as `module.name`, in order.
+pub fn imports(wasm: &[u8]) -> Vec<String> {
+    let mut res = Vec::new();
+    for payload in Parser::new(0).parse_all(wasm) {
+        if let Payload::ImportSection(s) = payload.unwrap() {
+            for import in s {
+                let import = import.unwrap();
+                res.push(format!("{}.{}", import.module, import.name));
+            }
+        }
+    }
+    res
+}
+
+/// Like `names`, without the indices.
+pub fn kept(wasm: &[u8], kind: &str) -> Vec<String> {
+    names(wasm, kind).into_iter().map(|(_, name)| name).collect()
+}
+
+/// Returns the contents of the data segments of `wasm`, in order.
+pub fn data(wasm: &[u8]) -> Vec<Vec<u8>> {
+    let mut res = Vec::new();
+    for payload in Parser::new(0).parse_all(wasm) {
+        if let Payload::DataSection(s) = payload.unwrap() {
+            for data in s {
+                res.push(data.unwrap().data.to_vec());
+            }
+        }
+    }
+    res
+}
+
+/// Returns the names of the functions that each element segment of `wasm`
+/// holds, in order.
+pub fn elements(wasm: &[u8]) -> Vec<Vec<String>> {
+    let funcs = names(wasm, "func");
+    let name = |i: u32| {
+        funcs.iter()
+            .find(|&&(j, _)| i == j)
+            .map(|(_, name)| name.clone())
+            .unwrap_or_else(|| i.to_string())
+    };
+    let mut res = Vec::new();
+    for payload in Parser::new(0).parse_all(wasm) {
+        if let Payload::ElementSection(s) = payload.unwrap() {
+            for seg in s {
+                let mut items = Vec::new();
+                match seg.unwrap().items {
+                    ElementItems::Functions(f) => {
+                        for i in f {
+                            items.push(name(i.unwrap()));
+                        }
+                    }
+                    ElementItems::Expressions(_, exprs) => {
+                        for expr in exprs {
+                            for op in expr.unwrap().get_operators_reader() {
+                                if let Operator::RefFunc { function_index } = op.unwrap() {
+                                    items.push(name(function_index));
+                                }
+                            }
+                        }
+                    }
+                }
+                res.push(items);
+            }
+        }
+    }
+    res
+}
+
+/// Returns the function types of the type section of `wasm` as
+/// `params -> results`, e.g. `[I32] -> [I64]`.
+pub fn func_types(wasm: &[u8]) -> Vec<String> {
+    let mut res = Vec::new();
+    for payload in Parser::new(0).parse_all(wasm) {
+        if let Payload::TypeSection(s) = payload.unwrap() {
+            for ty in s.into_iter_err_on_gc_types() {
+                let ty = ty.unwrap();
+                res.push(format!("{:?} -> {:?}", ty.params(), ty.results()));
+            }
+        }
+    }
+    res
+}
+
+/// Returns the recursion groups of the type section of `wasm`, with each
+/// type printed in the text format, e.g. `(struct (field i32))`.
+pub fn rec_groups(wasm: &[u8]) -> Vec<Vec<String>> {
+    let mut res = Vec::new();
+    for payload in Parser::new(0).parse_all(wasm) {
+        if let Payload::TypeSection(s) = payload.unwrap() {
+            for group in s {
+                let group = group.unwrap();
+                res.push(group.types().map(|ty| ty.to_string()).collect());
+            }
+        }
+    }
+    res
+}
+
+/// Returns the initial sizes of the tables that `wasm` defines, in order.
+pub fn tables(wasm: &[u8]) -> Vec<u64> {
+    let mut res = Vec::new();
+    for payload in Parser::new(0).parse_all(wasm) {
+        if let Payload::TableSection(s) = payload.unwrap() {
+            for table in s {
+                res.push(table.unwrap().ty.initial);
+            }
+        }
+    }
+    res
+}
+
+/// Returns the value types of the globals that `wasm` defines, in order.
+pub fn globals(wasm: &[u8]) -> Vec<String> {
+    let mut res = Vec::new();
+    for payload in Parser::new(0).parse_all(wasm) {
+        if let Payload::GlobalSection(s) = payload.unwrap() {
+            for global in s {
+                res.push(format!("{:?}", global.unwrap().ty.content_type));
+            }
+        }
+    }
+    res
+}
+
+/// Returns the initial sizes of the memories that `wasm` defines, in order.
+pub fn memories(wasm: &[u8]) -> Vec<u64> {
+    let mut res = Vec::new();
+    for payload in Parser::new(0).parse_all(wasm) {
+        if let Payload::MemorySection(s) = payload.unwrap() {
+            for memory in s {
+                res.push(memory.unwrap().initial);
+            }
+        }
+    }
+    res
+}
+
+/// Returns the types of the tags that `wasm` defines, in order, formatted
+/// like `func_types`.
+pub fn tags(wasm: &[u8]) -> Vec<String> {
+    let types = func_types(wasm);
+    let mut res = Vec::new();
+    for payload in Parser::new(0).parse_all(wasm) {
+        if let Payload::TagSection(s) = payload.unwrap() {
+            for tag in s {
+                res.push(types[tag.unwrap().func_type_idx as usize].clone());
+            }
+        }
+    }
+    res
+}
@@ -0,0 +1,205 @@
+extern crate gimli;
+extern crate wasm_encoder;
+extern crate wasm_gc;
+extern crate wasmparser;
+extern crate wat;
+
+mod common;
+
+use std::ops::Range;
+
+use common::{gc, validate};
+use gimli::write::{self, Address, AttributeValue, LineProgram, LineString};
+use gimli::{read, EndianSlice, LittleEndian};
+use wasm_encoder::Encode;
+use wasm_gc::{Config, Error};
+use wasmparser::{Parser, Payload};
+
+fn custom_sections(wasm: &[u8]) -> Vec<String> {
+    Parser::new(0)
+        .parse_all(wasm)
+        .filter_map(|payload| match payload.unwrap() {
+            Payload::CustomSection(s) => Some(s.name().to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn malformed_debug_info_is_an_error() {
+    let wasm = wat::parse_str(r#"
+        (module
+            (@custom ".debug_info" "\ff\ff\ff\ff\ff")
+            (@custom ".debug_line" "\01")
+            (@custom "other" "kept")
+            (func (export "f")))
+    "#).unwrap();
+    match Config::new().gc(&wasm) {
+        Err(Error::MalformedDebugInfo(_)) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn unrelated_debug_sections_are_copied() {
+    let output = gc(r#"
+        (module
+            (@custom ".debug_macinfo" "\00")
+            (func (export "f")))
+    "#);
+    assert!(custom_sections(&output).contains(&".debug_macinfo".to_string()));
+}
+
+#[test]
+fn debug_info_follows_the_code() {
+    let mut wasm = wat::parse_str(r#"
+        (module
+            (func $dead (result i32)
+                i32.const 3
+                i32.const 4
+                i32.mul)
+            (func $live (export "live") (result i32)
+                i32.const 1
+                i32.const 2
+                i32.add))
+    "#).unwrap();
+    let old = code_ranges(&wasm);
+    let (dead, live) = (old[0].clone(), old[1].clone());
+
+    // A compile unit with both functions, and a line table with a row at
+    // the start of each and one at the second instruction of `$live`.
+    let encoding = gimli::Encoding {
+        format: gimli::Format::Dwarf32,
+        version: 4,
+        address_size: 4,
+    };
+    let mut program = LineProgram::new(encoding,
+                                       gimli::LineEncoding::default(),
+                                       LineString::String(b"/src".to_vec()),
+                                       None,
+                                       LineString::String(b"lib.rs".to_vec()),
+                                       None);
+    let dir = program.default_directory();
+    let file = program.add_file(LineString::String(b"lib.rs".to_vec()), dir, None);
+    program.begin_sequence(Some(Address::Constant(dead.start)));
+    for &(address, line) in &[(dead.start, 5), (live.start, 10), (live.start + 3, 11)] {
+        program.row().address_offset = address - dead.start;
+        program.row().file = file;
+        program.row().line = line;
+        program.generate_row();
+    }
+    program.end_sequence(live.end - dead.start);
+
+    let mut dwarf = write::Dwarf::new();
+    let unit = dwarf.units.add(write::Unit::new(encoding, program));
+    let unit = dwarf.units.get_mut(unit);
+    let root = unit.root();
+    for &(name, range) in &[("dead", &dead), ("live", &live)] {
+        let id = unit.add(root, gimli::DW_TAG_subprogram);
+        let entry = unit.get_mut(id);
+        entry.set(gimli::DW_AT_name, AttributeValue::String(name.as_bytes().to_vec()));
+        entry.set(gimli::DW_AT_low_pc, AttributeValue::Address(Address::Constant(range.start)));
+        entry.set(gimli::DW_AT_high_pc, AttributeValue::Udata(range.end - range.start));
+    }
+    let mut sections = write::Sections::new(write::EndianVec::new(LittleEndian));
+    dwarf.write(&mut sections).unwrap();
+    sections.for_each(|id, data| {
+        if !data.slice().is_empty() {
+            wasm.push(0);
+            wasm_encoder::CustomSection {
+                name: id.name().into(),
+                data: data.slice().into(),
+            }.encode(&mut wasm);
+        }
+        Ok::<(), gimli::Error>(())
+    }).unwrap();
+    validate(&wasm).unwrap();
+
+    let output = Config::new().gc(&wasm).unwrap();
+    validate(&output).unwrap();
+    let new = code_ranges(&output);
+    assert_eq!(new.len(), 1);
+    assert_eq!(new[0].end - new[0].start, live.end - live.start);
+    assert!(new[0].start < live.start);
+    let info = read_debug_info(&output);
+    assert_eq!(info.subprograms, [("live".to_string(), new[0].clone())]);
+    assert_eq!(info.rows, [(new[0].start, 10), (new[0].start + 3, 11)]);
+    assert_eq!(info.end, new[0].end);
+}
+
+/// Returns the range of each function body of `wasm`, relative to the
+/// contents of the code section.
+fn code_ranges(wasm: &[u8]) -> Vec<Range<u64>> {
+    let mut code_offset = 0;
+    let mut res = Vec::new();
+    for payload in Parser::new(0).parse_all(wasm) {
+        match payload.unwrap() {
+            Payload::CodeSectionStart { range, .. } => code_offset = range.start,
+            Payload::CodeSectionEntry(body) => {
+                let range = body.range();
+                res.push((range.start - code_offset) as u64..(range.end - code_offset) as u64);
+            }
+            _ => {}
+        }
+    }
+    res
+}
+
+struct DebugInfo {
+    /// The name and code range of each subprogram.
+    subprograms: Vec<(String, Range<u64>)>,
+    /// The address and line of each line table row.
+    rows: Vec<(u64, u64)>,
+    /// The end of the line table's only sequence.
+    end: u64,
+}
+
+/// Reads the DWARF of `wasm`.
+fn read_debug_info(wasm: &[u8]) -> DebugInfo {
+    let mut customs = Vec::new();
+    for payload in Parser::new(0).parse_all(wasm) {
+        if let Payload::CustomSection(s) = payload.unwrap() {
+            customs.push((s.name().to_string(), s.data()));
+        }
+    }
+    let dwarf = read::Dwarf::load(|id| -> Result<_, gimli::Error> {
+        let data = customs.iter()
+            .find(|(name, _)| name == id.name())
+            .map(|(_, data)| *data)
+            .unwrap_or(&[]);
+        Ok(EndianSlice::new(data, LittleEndian))
+    }).unwrap();
+
+    let mut subprograms = Vec::new();
+    let mut rows = Vec::new();
+    let mut end = None;
+    let mut units = dwarf.units();
+    while let Some(header) = units.next().unwrap() {
+        let unit = dwarf.unit(header).unwrap();
+        let mut entries = unit.entries();
+        while let Some(entry) = entries.next_dfs().unwrap() {
+            if entry.tag() != gimli::DW_TAG_subprogram {
+                continue
+            }
+            let name = entry.attr_value(gimli::DW_AT_name).unwrap();
+            let name = dwarf.attr_string(&unit, name).unwrap();
+            let low = match entry.attr_value(gimli::DW_AT_low_pc) {
+                Some(read::AttributeValue::Addr(a)) => a,
+                other => panic!("unexpected low_pc: {:?}", other),
+            };
+            let high = entry.attr(gimli::DW_AT_high_pc).unwrap().udata_value().unwrap();
+            subprograms.push((name.to_string_lossy().into_owned(), low..low + high));
+        }
+        let program = unit.line_program.clone().unwrap();
+        let mut program_rows = program.rows();
+        while let Some((_, row)) = program_rows.next_row().unwrap() {
+            if row.end_sequence() {
+                assert_eq!(end, None);
+                end = Some(row.address());
+            } else {
+                rows.push((row.address(), row.line().unwrap().get()));
+            }
+        }
+    }
+    DebugInfo { subprograms, rows, end: end.unwrap() }
+}
@@ -0,0 +1,80 @@
+extern crate wasm_gc;
+extern crate wat;
+
+use std::error::Error as StdError;
+
+use wasm_gc::{Config, Error};
+
+fn gc(wat: &str) -> Result<Vec<u8>, Error> {
+    Config::new().gc(&wat::parse_str(wat).unwrap())
+}
+
+#[test]
+fn truncated_module() {
+    let err = match Config::new().gc(b"\0asm\x01\0\0\0\x01\x05") {
+        Err(err @ Error::Parse(_)) => err,
+        other => panic!("unexpected result: {:?}", other),
+    };
+    // The parser's error is the source, for error reporters that walk the
+    // chain.
+    assert!(err.source().is_some());
+}
+
+#[test]
+fn out_of_range_index() {
+    let err = match gc(r#"(module (func (export "f") call 5))"#) {
+        Err(err @ Error::IndexOutOfRange { kind: "function", index: 5 }) => err,
+        other => panic!("unexpected result: {:?}", other),
+    };
+    assert!(err.source().is_none());
+    match gc(r#"(module (global i32 (i32.const 0)) (func (export "f") global.get 1 drop))"#) {
+        Err(Error::IndexOutOfRange { kind: "global", index: 1 }) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn missing_section() {
+    match gc(r#"(module (func (export "f") global.get 0 drop))"#) {
+        Err(Error::MissingSection("global")) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+    match gc(r#"(module (func (export "f") data.drop 0))"#) {
+        Err(Error::MissingSection("data segment")) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+    match gc(r#"(module (func (export "f") elem.drop 0))"#) {
+        Err(Error::MissingSection("element segment")) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+    match gc(r#"(module (func (export "f") throw 0))"#) {
+        Err(Error::MissingSection("tag")) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+    // There's no function section at all for the start function to be in.
+    match gc("(module (start 0))") {
+        Err(Error::MissingSection("function")) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn start_function_without_code() {
+    // A function section declaring the start function, but no code section.
+    let module = b"\0asm\x01\0\0\0\
+        \x01\x04\x01\x60\0\0\
+        \x03\x02\x01\0\
+        \x08\x01\0";
+    match Config::new().gc(module) {
+        Err(Error::MissingSection("code")) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn malformed_name_section() {
+    match gc(r#"(module (@custom "name" "\01\ff\00") (func (export "f")))"#) {
+        Err(Error::MalformedNameSection(_)) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
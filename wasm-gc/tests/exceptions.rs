@@ -0,0 +1,51 @@
+extern crate wasm_gc;
+extern crate wasmparser;
+extern crate wat;
+
+mod common;
+
+use common::{func_types, gc, imports, tags};
+
+// The tags are told apart by their type.
+
+#[test]
+fn unused_tags_are_removed() {
+    let output = gc(r#"
+        (module
+            (import "env" "imported" (tag (param i32)))
+            (import "env" "unused_import" (tag))
+            (type (func (param f64)))
+            (tag (type 0))
+            (tag (param i64))
+            (tag)
+            (tag (export "exported") (param f32))
+            (func (export "run") (param i32)
+                block
+                    try_table (catch 4 0)
+                        local.get 0
+                        throw 0
+                    end
+                end
+                i64.const 0
+                throw 3))
+    "#);
+    assert_eq!(imports(&output), ["env.imported"]);
+    assert_eq!(tags(&output), ["[I64] -> []", "[] -> []", "[F32] -> []"]);
+    assert!(!func_types(&output).contains(&"[F64] -> []".to_string()));
+}
+
+#[test]
+fn legacy_exception_handling() {
+    let output = gc(r#"
+        (module
+            (tag (param i64))
+            (tag (param i32))
+            (func (export "run") (result i32)
+                try (result i32)
+                    i32.const 1
+                    throw 1
+                catch 1
+                end))
+    "#);
+    assert_eq!(tags(&output), ["[I32] -> []"]);
+}
@@ -0,0 +1,143 @@
+extern crate wasm_gc;
+extern crate wasmparser;
+extern crate wat;
+
+mod common;
+
+use common::{exports, gc_with, kept};
+use wasm_gc::{Config, Error, COMPILER_RT_BLACKLIST};
+
+const MODULE: &str = r#"
+    (module
+        (func $main (export "main"))
+        (func $memcpy (export "memcpy"))
+        (func $api (export "api")
+            call $helper)
+        (func $helper))
+"#;
+
+/// Returns the exports and functions that are left of `MODULE` after a gc
+/// pass with `config`.
+fn gc(config: &mut Config) -> (Vec<String>, Vec<String>) {
+    let output = gc_with(config, MODULE);
+    (exports(&output), kept(&output, "func"))
+}
+
+#[test]
+fn default_blacklist_drops_compiler_rt_exports() {
+    let (exports, funcs) = gc(&mut Config::new());
+    assert_eq!(exports, ["api"]);
+    assert_eq!(funcs, ["api", "helper"]);
+}
+
+#[test]
+fn unblacklist_keeps_export() {
+    let (exports, funcs) = gc(Config::new().unblacklist("main"));
+    assert_eq!(exports, ["main", "api"]);
+    assert_eq!(funcs, ["main", "api", "helper"]);
+}
+
+#[test]
+fn blacklist_drops_export_and_its_code() {
+    let (exports, funcs) = gc(Config::new().blacklist("api"));
+    assert!(exports.is_empty());
+    assert!(funcs.is_empty());
+}
+
+#[test]
+fn set_blacklist_replaces_preset() {
+    let (exports, _) = gc(Config::new().set_blacklist(vec!["api"]));
+    assert_eq!(exports, ["main", "memcpy"]);
+
+    let (exports, _) = gc(Config::new()
+        .clear_blacklist()
+        .set_blacklist(COMPILER_RT_BLACKLIST.iter().cloned()));
+    assert_eq!(exports, ["api"]);
+}
+
+#[test]
+fn clear_blacklist_keeps_every_export() {
+    let (exports, _) = gc(Config::new().clear_blacklist());
+    assert_eq!(exports, ["main", "memcpy", "api"]);
+}
+
+#[test]
+fn keep_overrides_blacklist() {
+    let (exports, _) = gc(Config::new().keep_export("main"));
+    assert_eq!(exports, ["main", "api"]);
+
+    let (exports, _) = gc(Config::new().keep("mem*"));
+    assert_eq!(exports, ["memcpy", "api"]);
+}
+
+#[test]
+fn remove_export_collects_its_code() {
+    let (exports, funcs) = gc(Config::new().remove_export("a*"));
+    assert!(exports.is_empty());
+    assert!(funcs.is_empty());
+
+    let (exports, funcs) = gc(Config::new().clear_blacklist().remove_export("m*"));
+    assert_eq!(exports, ["api"]);
+    assert_eq!(funcs, ["api", "helper"]);
+}
+
+#[test]
+fn keep_overrides_remove_export() {
+    let (exports, funcs) = gc(Config::new().remove_export("*").keep_export("api"));
+    assert_eq!(exports, ["api"]);
+    assert_eq!(funcs, ["api", "helper"]);
+
+    let (exports, _) = gc(Config::new().remove_export("*").keep("ap?"));
+    assert_eq!(exports, ["api"]);
+}
+
+#[test]
+fn allowlist_strips_unlisted_exports() {
+    let (exports, funcs) = gc(Config::new().export_allowlist(vec!["api"]));
+    assert_eq!(exports, ["api"]);
+    assert_eq!(funcs, ["api", "helper"]);
+}
+
+#[test]
+fn allowlist_overrides_blacklist_and_keep() {
+    let (exports, _) = gc(Config::new().export_allowlist(vec!["m*"]));
+    assert_eq!(exports, ["main", "memcpy"]);
+
+    let (exports, _) = gc(Config::new().export_allowlist(vec!["api"]).keep_export("main"));
+    assert_eq!(exports, ["api"]);
+}
+
+#[test]
+fn remove_export_overrides_allowlist() {
+    let (exports, funcs) = gc(Config::new().export_allowlist(vec!["*"]).remove_export("m*"));
+    assert_eq!(exports, ["api"]);
+    assert_eq!(funcs, ["api", "helper"]);
+
+    // `keep_export` still wins over both.
+    let (exports, _) = gc(Config::new()
+        .export_allowlist(vec!["*"])
+        .remove_export("*")
+        .keep_export("main"));
+    assert_eq!(exports, ["main"]);
+}
+
+#[test]
+fn reject_unlisted_exports() {
+    let input = wat::parse_str(MODULE).unwrap();
+    let err = Config::new()
+        .export_allowlist(vec!["api"])
+        .reject_unlisted_exports(true)
+        .gc(&input)
+        .unwrap_err();
+    match err {
+        Error::UnlistedExports(names) => assert_eq!(names, ["main", "memcpy"]),
+        e => panic!("unexpected error: {}", e),
+    }
+
+    let output = Config::new()
+        .export_allowlist(vec!["*"])
+        .reject_unlisted_exports(true)
+        .gc(&input)
+        .unwrap();
+    assert_eq!(exports(&output), ["main", "memcpy", "api"]);
+}
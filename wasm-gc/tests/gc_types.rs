@@ -0,0 +1,57 @@
+extern crate wasm_gc;
+extern crate wasmparser;
+extern crate wat;
+
+mod common;
+
+use common::{gc, rec_groups};
+
+#[test]
+fn rec_groups_are_kept_whole() {
+    let output = gc(r#"
+        (module
+            (type (array i8))
+            (rec
+                (type (struct (field (ref null 2))))
+                (type (struct (field (ref null 1))))
+                (type (struct (field (ref 0)))))
+            (rec
+                (type (struct (field (ref null 5))))
+                (type (struct (field (ref null 4)))))
+            (type (struct (field i32)))
+            (func (export "new") (result (ref 1))
+                ref.null 2
+                struct.new 1)
+            (func (result (ref 6))
+                i32.const 0
+                struct.new 6))
+    "#);
+    assert_eq!(rec_groups(&output), vec![
+        vec!["(array i8)"],
+        vec![
+            "(struct (ref null (module 2)))",
+            "(struct (ref null (module 1)))",
+            "(struct (ref (module 0)))",
+        ],
+        vec!["(func (result (ref (module 1))))"],
+    ]);
+}
+
+#[test]
+fn subtypes_keep_their_supertype() {
+    let output = gc(r#"
+        (module
+            (type (sub (struct (field i32))))
+            (type (sub final 0 (struct (field i32) (field i64))))
+            (type (sub (struct)))
+            (func (export "new") (result (ref 1))
+                i32.const 0
+                i64.const 1
+                struct.new 1))
+    "#);
+    assert_eq!(rec_groups(&output), vec![
+        vec!["(sub (struct i32))"],
+        vec!["(sub final (module 0) (struct i32 i64))"],
+        vec!["(func (result (ref (module 1))))"],
+    ]);
+}
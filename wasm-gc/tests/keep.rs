@@ -0,0 +1,52 @@
+extern crate wasm_gc;
+extern crate wasmparser;
+extern crate wat;
+
+mod common;
+
+use common::{gc_with, kept};
+use wasm_gc::Config;
+
+const MODULE: &str = r#"
+    (module
+        (import "env" "ext" (func $ext))
+        (import "env" "other" (func $other))
+        (func $_ZN3foo3bar17h0123456789abcdefE
+            call $callee)
+        (func $callee)
+        (func $unused)
+        (func $api (export "api")))
+"#;
+
+fn funcs(config: &mut Config) -> Vec<String> {
+    kept(&gc_with(config, MODULE), "func")
+}
+
+#[test]
+fn unreferenced_functions_are_removed() {
+    assert_eq!(funcs(&mut Config::new()), ["api"]);
+}
+
+#[test]
+fn keep_function_index() {
+    // Imported functions come first in the index space.
+    assert_eq!(funcs(Config::new().keep_function_index(4)), ["unused", "api"]);
+    assert_eq!(funcs(Config::new().keep_function_index(1)), ["other", "api"]);
+}
+
+#[test]
+fn keep_mangled_name() {
+    assert_eq!(funcs(Config::new().keep("_ZN3foo*")),
+               ["foo::bar::h0123456789abcdef", "callee", "api"]);
+}
+
+#[test]
+fn keep_demangled_name() {
+    assert_eq!(funcs(Config::new().keep("foo::bar*")),
+               ["foo::bar::h0123456789abcdef", "callee", "api"]);
+}
+
+#[test]
+fn keep_imported_function() {
+    assert_eq!(funcs(Config::new().keep("ext")), ["ext", "api"]);
+}
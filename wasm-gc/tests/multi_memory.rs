@@ -0,0 +1,46 @@
+extern crate wasm_gc;
+extern crate wasmparser;
+extern crate wat;
+
+mod common;
+
+use common::{data, gc, memories};
+
+// The memories are told apart by their initial size.
+
+#[test]
+fn unused_memories_are_removed_with_their_segments() {
+    let output = gc(r#"
+        (module
+            (import "env" "shared" (memory 1))
+            (memory 2)
+            (memory 3)
+            (data (memory 0) (i32.const 0) "a")
+            (data (memory 1) (i32.const 0) "b")
+            (data (memory 2) (i32.const 0) "c")
+            (func (export "load") (result i32)
+                i32.const 0
+                i32.load 2))
+    "#);
+    assert_eq!(memories(&output), [3]);
+    assert_eq!(data(&output), [b"a".to_vec(), b"c".to_vec()]);
+}
+
+#[test]
+fn memory_indices_are_renumbered() {
+    let output = gc(r#"
+        (module
+            (memory 1)
+            (memory 2)
+            (memory 3)
+            (func (export "copy")
+                i32.const 0
+                i32.const 0
+                i32.const 1
+                memory.copy 2 1
+                i32.const 1
+                memory.grow 2
+                drop))
+    "#);
+    assert_eq!(memories(&output), [2, 3]);
+}
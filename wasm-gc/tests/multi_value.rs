@@ -0,0 +1,38 @@
+extern crate wasm_gc;
+extern crate wasmparser;
+extern crate wat;
+
+mod common;
+
+use common::{func_types, gc, kept};
+
+#[test]
+fn multi_value_results_and_block_types() {
+    let output = gc(r#"
+        (module
+            (type (func (result i32 i64)))
+            (type (func (param i32 i64) (result i64 i32)))
+            (type (func (param f32) (result f32 f32)))
+            (func $make (type 0)
+                i32.const 1
+                i64.const 2)
+            (func $swapper (export "swap") (type 1)
+                local.get 1
+                local.get 0)
+            (func (export "run") (result i64 i32)
+                call $make
+                block (type 1) (param i32 i64) (result i64 i32)
+                    call $swapper
+                end)
+            (func $dead (type 2)
+                local.get 0
+                local.get 0))
+    "#);
+    assert_eq!(kept(&output, "func"), ["make", "swapper"]);
+    // The type of `run` is added after the explicit ones.
+    assert_eq!(func_types(&output), [
+        "[] -> [I32, I64]",
+        "[I32, I64] -> [I64, I32]",
+        "[] -> [I64, I32]",
+    ]);
+}
@@ -0,0 +1,66 @@
+extern crate wasm_gc;
+extern crate wasmparser;
+extern crate wat;
+
+mod common;
+
+use common::{gc, indirect_names, kept, names};
+use wasmparser::{BinaryReader, Name, NameSectionReader, Parser, Payload};
+
+fn named(names: &[(u32, &str)]) -> Vec<(u32, String)> {
+    names.iter().map(|&(i, name)| (i, name.to_string())).collect()
+}
+
+#[test]
+fn locals_labels_and_fields_follow_their_function_or_type() {
+    let output = gc(r#"
+        (module
+            (type $dead_t (struct (field $x i32)))
+            (type $live_t (struct (field $y i64) (field $z i32)))
+            (func $dead (param $a i32) (local $b i64)
+                block $outer
+                end
+                i32.const 0
+                struct.new $dead_t
+                drop)
+            (func $live (export "live") (param $c i32) (result (ref $live_t))
+                block $inner
+                end
+                i64.const 0
+                i32.const 0
+                struct.new $live_t))
+    "#);
+    assert_eq!(names(&output, "func"), named(&[(0, "live")]));
+    assert_eq!(kept(&output, "type"), ["live_t"]);
+    assert_eq!(indirect_names(&output, "local"), [(0, named(&[(0, "c")]))]);
+    assert_eq!(indirect_names(&output, "label"), [(0, named(&[(0, "inner")]))]);
+    assert_eq!(indirect_names(&output, "field"), [(0, named(&[(0, "y"), (1, "z")]))]);
+}
+
+#[test]
+fn unknown_subsections_are_passed_through() {
+    // Function names for both functions, then a subsection with the
+    // unassigned id 0x20.
+    let output = gc(r#"
+        (module
+            (@custom "name" "\01\0d\02\00\04dead\01\04live\20\03xyz")
+            (func)
+            (func (export "live")))
+    "#);
+    assert_eq!(names(&output, "func"), named(&[(0, "live")]));
+
+    let mut unknown = Vec::new();
+    for payload in Parser::new(0).parse_all(&output) {
+        let s = match payload.unwrap() {
+            Payload::CustomSection(ref s) if s.name() == "name" => s.clone(),
+            _ => continue,
+        };
+        let data = BinaryReader::new(s.data(), s.data_offset());
+        for subsection in NameSectionReader::new(data) {
+            if let Name::Unknown { ty, data, .. } = subsection.unwrap() {
+                unknown.push((ty, data.to_vec()));
+            }
+        }
+    }
+    assert_eq!(unknown, [(0x20, b"xyz".to_vec())]);
+}
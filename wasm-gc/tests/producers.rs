@@ -0,0 +1,109 @@
+extern crate wasm_gc;
+extern crate wasmparser;
+extern crate wat;
+
+mod common;
+
+use common::{gc, gc_with};
+use wasm_gc::Config;
+use wasmparser::{BinaryReader, Parser, Payload, ProducersSectionReader};
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// A field of a `producers` section with the name and version of each of
+/// its values.
+type Field = (String, Vec<(String, String)>);
+
+/// Returns the fields of each `producers` section of `wasm`.
+fn producers(wasm: &[u8]) -> Vec<Vec<Field>> {
+    let mut res = Vec::new();
+    for payload in Parser::new(0).parse_all(wasm) {
+        let s = match payload.unwrap() {
+            Payload::CustomSection(ref s) if s.name() == "producers" => s.clone(),
+            _ => continue,
+        };
+        let reader = BinaryReader::new(s.data(), s.data_offset());
+        let fields = ProducersSectionReader::new(reader).unwrap().into_iter().map(|field| {
+            let field = field.unwrap();
+            let values = field.values.into_iter().map(|value| {
+                let value = value.unwrap();
+                (value.name.to_string(), value.version.to_string())
+            });
+            (field.name.to_string(), values.collect())
+        });
+        res.push(fields.collect());
+    }
+    res
+}
+
+fn field(name: &str, values: &[(&str, &str)]) -> Field {
+    let values = values.iter().map(|&(name, version)| (name.to_string(), version.to_string()));
+    (name.to_string(), values.collect())
+}
+
+#[test]
+fn added_to_modules_without_producers() {
+    let output = gc("(module (func (export \"f\")))");
+    assert_eq!(producers(&output), [[field("processed-by", &[("wasm-gc", VERSION)])]]);
+}
+
+#[test]
+fn merged_into_processed_by() {
+    let output = gc(r#"
+        (module
+            (@producers
+                (language "Rust" "")
+                (processed-by "rustc" "1.0.0"))
+            (func (export "f")))
+    "#);
+    assert_eq!(producers(&output), [[
+        field("language", &[("Rust", "")]),
+        field("processed-by", &[("rustc", "1.0.0"), ("wasm-gc", VERSION)]),
+    ]]);
+}
+
+#[test]
+fn replaces_older_versions() {
+    let output = gc(r#"
+        (module
+            (@producers
+                (processed-by "wasm-gc" "0.0.1")
+                (processed-by "wasm-bindgen" "0.2.0"))
+            (func (export "f")))
+    "#);
+    assert_eq!(producers(&output), [[
+        field("processed-by", &[("wasm-gc", VERSION), ("wasm-bindgen", "0.2.0")]),
+    ]]);
+}
+
+#[test]
+fn not_recorded_if_disabled() {
+    let wat = r#"
+        (module
+            (@producers (processed-by "rustc" "1.0.0"))
+            (func (export "f")))
+    "#;
+    let output = gc_with(Config::new().record_processed_by(false), wat);
+    assert_eq!(producers(&output), [[field("processed-by", &[("rustc", "1.0.0")])]]);
+
+    let output = gc_with(Config::new().record_processed_by(false),
+                         "(module (func (export \"f\")))");
+    assert_eq!(producers(&output), Vec::<Vec<_>>::new());
+}
+
+#[test]
+fn malformed_producers_are_copied() {
+    let output = gc(r#"
+        (module
+            (@custom "producers" "\01\ff")
+            (func (export "f")))
+    "#);
+    let sections = Parser::new(0)
+        .parse_all(&output)
+        .filter_map(|payload| match payload.unwrap() {
+            Payload::CustomSection(ref s) if s.name() == "producers" => Some(s.data().to_vec()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(sections, [vec![0x01, 0xff]]);
+}
@@ -0,0 +1,108 @@
+extern crate wasm_gc;
+extern crate wasmparser;
+extern crate wat;
+
+mod common;
+
+// Proposals that need no handling of their own beyond the reencoder's, each
+// checked on a module mixing live and dead items.
+
+use common::{data, func_types, gc, globals, kept, memories, tables};
+use wasmparser::{Operator, Parser, Payload};
+
+#[test]
+fn i64_memories_and_tables_keep_their_offsets() {
+    // The memories and tables are told apart by their initial size.
+    let output = gc(r#"
+        (module
+            (memory i64 1)
+            (memory i64 2 3)
+            (table i64 1 funcref)
+            (table i64 2 funcref)
+            (global i64 (i64.const 8))
+            (data (memory 1) (global.get 0) "a")
+            (data (memory 1) (i64.const 16) "b")
+            (data (memory 0) (i64.const 0) "c")
+            (func (export "load") (result i32)
+                i64.const 0
+                i32.load 1 offset=4294967296
+                i64.const 0
+                table.get 1
+                drop))
+    "#);
+    assert_eq!(memories(&output), [2]);
+    assert_eq!(tables(&output), [2]);
+    assert_eq!(data(&output), [b"a".to_vec(), b"b".to_vec()]);
+
+    let mut offsets = Vec::new();
+    for payload in Parser::new(0).parse_all(&output) {
+        if let Payload::CodeSectionEntry(body) = payload.unwrap() {
+            for op in body.get_operators_reader().unwrap() {
+                if let Operator::I32Load { memarg } = op.unwrap() {
+                    offsets.push((memarg.memory, memarg.offset));
+                }
+            }
+        }
+    }
+    assert_eq!(offsets, [(0, 1 << 32)]);
+}
+
+#[test]
+fn tail_callees_are_kept() {
+    let output = gc(r#"
+        (module
+            (type (func (param i32) (result i32)))
+            (type (func (result i32)))
+            (type (func (result i64)))
+            (type (func (param f32)))
+            (table 1 funcref)
+            (elem (table 0) (i32.const 0) func $indirect)
+            (elem declare func $by_ref)
+            (func $direct (type 0)
+                local.get 0)
+            (func $indirect (type 1)
+                i32.const 1)
+            (func $by_ref (type 2)
+                i64.const 2)
+            (func $unreachable (type 3))
+            (func (export "direct") (result i32)
+                i32.const 0
+                return_call $direct)
+            (func (export "indirect") (result i32)
+                i32.const 0
+                return_call_indirect 0 (type 1))
+            (func (export "by_ref") (result i64)
+                ref.func $by_ref
+                return_call_ref 2))
+    "#);
+    assert_eq!(kept(&output, "func"), ["direct", "indirect", "by_ref"]);
+    assert_eq!(func_types(&output), ["[I32] -> [I32]", "[] -> [I32]", "[] -> [I64]"]);
+}
+
+#[test]
+fn simd_values_and_memory_ops() {
+    let output = gc(r#"
+        (module
+            (memory 1)
+            (memory 2)
+            (global v128 (v128.const i32x4 0 0 0 0))
+            (global (mut v128) (v128.const i32x4 1 2 3 4))
+            (func (export "run") (param $x v128) (result v128)
+                (local $tmp v128)
+                i32.const 0
+                v128.load 1
+                local.get $x
+                i32x4.add
+                local.set $tmp
+                i32.const 16
+                local.get $tmp
+                v128.store 1
+                i32.const 32
+                global.get 1
+                v128.load32_lane 1 1
+                v128.const i64x2 0 0
+                i8x16.shuffle 0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15))
+    "#);
+    assert_eq!(memories(&output), [2]);
+    assert_eq!(globals(&output), ["V128"]);
+}
@@ -0,0 +1,132 @@
+extern crate wasm_gc;
+extern crate wasmparser;
+extern crate wat;
+
+mod common;
+
+use common::{elements, gc, globals, kept, tables};
+use wasmparser::{ElementItems, ElementKind, Parser, Payload};
+
+#[test]
+fn ref_func_keeps_declared_functions() {
+    let output = gc(r#"
+        (module
+            (elem declare func $target)
+            (func $target)
+            (func $dead)
+            (func (export "get") (result funcref)
+                ref.func $target))
+    "#);
+    assert_eq!(kept(&output, "func"), ["target"]);
+    assert_eq!(elements(&output), [["target"]]);
+}
+
+#[test]
+fn unused_tables_are_removed() {
+    // The tables are told apart by their initial size.
+    let output = gc(r#"
+        (module
+            (table 1 funcref)
+            (table 2 externref)
+            (table 3 funcref)
+            (elem (table 0) (i32.const 0) func $in_dead)
+            (elem (table 2) (i32.const 0) func $in_live)
+            (func $in_dead)
+            (func $in_live)
+            (func (export "set") (param externref)
+                i32.const 0
+                local.get 0
+                table.set 1
+                i32.const 0
+                call_indirect 2))
+    "#);
+    assert_eq!(tables(&output), [2, 3]);
+    assert_eq!(elements(&output), [["in_live"]]);
+    assert_eq!(kept(&output, "func"), ["in_live"]);
+}
+
+#[test]
+fn table_operators_are_remapped() {
+    let output = gc(r#"
+        (module
+            (table 1 externref)
+            (table 2 externref)
+            (func (export "grow") (param externref) (result i32)
+                local.get 0
+                i32.const 1
+                table.grow 1
+                drop
+                i32.const 0
+                local.get 0
+                i32.const 1
+                table.fill 1
+                i32.const 0
+                i32.const 0
+                i32.const 1
+                table.copy 1 1
+                table.size 1))
+    "#);
+    assert_eq!(tables(&output), [2]);
+}
+
+#[test]
+fn ref_func_declared_by_dead_global() {
+    let output = gc(r#"
+        (module
+            (global funcref (ref.func $f))
+            (func $f)
+            (func $get (export "get") (result funcref)
+                ref.func $f))
+    "#);
+    assert!(globals(&output).is_empty());
+    assert_eq!(kept(&output, "func"), ["f", "get"]);
+}
+
+#[test]
+fn ref_func_declared_by_blacklisted_export() {
+    let output = gc(r#"
+        (module
+            (func $f (export "main"))
+            (func $get (export "get") (result funcref)
+                ref.func $f))
+    "#);
+    assert_eq!(common::exports(&output), ["get"]);
+    assert_eq!(kept(&output, "func"), ["f", "get"]);
+}
+
+#[test]
+fn ref_func_declared_once() {
+    // `$f` is still declared by the kept global, `$g` only by the removed
+    // export.
+    let output = gc(r#"
+        (module
+            (global (export "live") funcref (ref.func $f))
+            (func $f)
+            (func $g (export "main"))
+            (func $get (export "get") (result funcref)
+                ref.func $f
+                drop
+                ref.func $g))
+    "#);
+    assert_eq!(kept(&output, "func"), ["f", "g", "get"]);
+    assert_eq!(declared_functions(&output), [1]);
+}
+
+/// Returns the functions that the declarative element segments of `wasm`
+/// declare.
+fn declared_functions(wasm: &[u8]) -> Vec<u32> {
+    let mut res = Vec::new();
+    for payload in Parser::new(0).parse_all(wasm) {
+        if let Payload::ElementSection(s) = payload.unwrap() {
+            for seg in s {
+                let seg = seg.unwrap();
+                let funcs = match (seg.kind, seg.items) {
+                    (ElementKind::Declared, ElementItems::Functions(funcs)) => funcs,
+                    _ => continue,
+                };
+                res.extend(funcs.into_iter().map(|f| f.unwrap()));
+            }
+        }
+    }
+    res
+}
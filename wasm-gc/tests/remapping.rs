@@ -0,0 +1,57 @@
+extern crate wasm_gc;
+extern crate wasmparser;
+extern crate wat;
+
+mod common;
+
+use common::names;
+use wasm_gc::{Config, Item, Remapping};
+
+const MODULE: &str = r#"
+    (module
+        (import "env" "a" (func $imp_a))
+        (import "env" "b" (func $imp_b))
+        (import "env" "g" (global $g i32))
+        (import "env" "h" (global $h i32))
+        (global $own i32 (global.get $h))
+        (func $f1)
+        (func $f2 (export "f2")
+            call $imp_a
+            global.get $own
+            drop)
+        (func $f3)
+        (func $f4 (export "f4")))
+"#;
+
+/// Checks that every item of `kind` named in the input ends up where
+/// `remapping` says, and that it maps back to its input index.
+fn check(input: &[u8], output: &[u8], kind: &str, item: fn(u32) -> Item, remapping: &Remapping) {
+    let output_names = names(output, kind);
+    for (old, name) in names(input, kind) {
+        let expected = output_names.iter()
+            .find(|(_, n)| *n == name)
+            .map(|&(i, _)| i);
+        assert_eq!(remapping.new_index(item(old)), expected, "{}", name);
+        if let Some(new) = expected {
+            assert_eq!(remapping.old_index(item(new)), Some(old), "{}", name);
+        }
+    }
+}
+
+#[test]
+fn remapping_matches_output() {
+    let input = wat::parse_str(MODULE).unwrap();
+    let (output, analysis) = Config::new().gc_with_analysis(&input).unwrap();
+    common::validate(&output).unwrap();
+    let remapping = analysis.remapping();
+    check(&input, &output, "func", Item::Function, remapping);
+    check(&input, &output, "global", Item::Global, remapping);
+
+    assert_eq!(remapping.functions, [Some(0), None, None, Some(1), None, Some(2)]);
+    assert_eq!(remapping.globals, [None, Some(0), Some(1)]);
+    assert_eq!(remapping.new_index(Item::Function(1)), None);
+    assert_eq!(remapping.old_index(Item::Function(2)), Some(5));
+    assert_eq!(remapping.old_index(Item::Function(3)), None);
+    // Exports aren't renumbered through the remapping.
+    assert_eq!(remapping.new_index(Item::Export(0)), None);
+}
@@ -0,0 +1,76 @@
+extern crate wasm_gc;
+extern crate wasmparser;
+extern crate wat;
+
+mod common;
+
+use wasm_gc::{Config, ReportItem};
+
+const MODULE: &str = r#"
+    (module
+        (import "env" "log" (func $log (param i32)))
+        (import "env" "unused" (func $unused_import))
+        (global $counter (mut i32) (i32.const 0))
+        (global $dead i32 (i32.const 7))
+        (func $_ZN3foo3bar17h0123456789abcdefE (export "run")
+            global.get $counter
+            call $log)
+        (func $dead_fn)
+        (func $main (export "main")))
+"#;
+
+fn items(items: &[ReportItem]) -> Vec<(u32, Option<&str>, usize, bool)> {
+    items.iter()
+        .map(|item| (item.index, item.name.as_ref().map(|s| &s[..]), item.size, item.kept))
+        .collect()
+}
+
+#[test]
+fn report_lists_every_item() {
+    let input = wat::parse_str(MODULE).unwrap();
+    let (output, report) = Config::new().gc_with_report(&input).unwrap();
+    common::validate(&output).unwrap();
+
+    assert_eq!(report.input_size, input.len());
+    assert_eq!(report.output_size, Some(output.len()));
+    assert_eq!(items(&report.functions), [
+        (2, Some("foo::bar::h0123456789abcdef"), 7, true),
+        (3, Some("dead_fn"), 3, false),
+        (4, Some("main"), 3, false),
+    ]);
+    assert_eq!(items(&report.imports), [
+        (0, Some("env.log"), 10, true),
+        (1, Some("env.unused"), 13, false),
+    ]);
+    assert_eq!(items(&report.exports), [
+        (0, Some("run"), 6, true),
+        (1, Some("main"), 7, false),
+    ]);
+    assert_eq!(items(&report.globals), [
+        (0, Some("counter"), 5, true),
+        (1, Some("dead"), 5, false),
+    ]);
+    assert_eq!(items(&report.types), [(0, None, 4, true), (1, None, 3, true)]);
+    assert_eq!(report.removed_size(), 3 + 3 + 13 + 7 + 5);
+}
+
+#[test]
+fn report_names_follow_demangle() {
+    let input = wat::parse_str(MODULE).unwrap();
+    let (_, report) = Config::new().demangle(false).gc_with_report(&input).unwrap();
+    assert_eq!(report.functions[0].name.as_ref().unwrap(), "_ZN3foo3bar17h0123456789abcdefE");
+}
+
+#[test]
+fn dry_run_estimates_savings() {
+    let input = wat::parse_str(MODULE).unwrap();
+    let analysis = Config::new().analyze(&input).unwrap();
+    let report = analysis.report();
+    assert_eq!(report.input_size, input.len());
+    assert_eq!(report.output_size, None);
+    assert_eq!(report.removed_size(), 3 + 3 + 13 + 7 + 5);
+
+    // The estimate leaves out section headers and the name section.
+    let output = Config::new().record_processed_by(false).gc(&input).unwrap();
+    assert!(report.removed_size() <= input.len() - output.len());
+}
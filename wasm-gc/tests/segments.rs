@@ -0,0 +1,145 @@
+extern crate wasm_gc;
+extern crate wasmparser;
+extern crate wat;
+
+mod common;
+
+use common::{data, elements, gc, kept, memories, tables};
+use wasmparser::{Parser, Payload};
+
+#[test]
+fn unused_passive_data_is_removed() {
+    let output = gc(r#"
+        (module
+            (memory 1)
+            (data (i32.const 0) "active")
+            (data "unused")
+            (data "used")
+            (func (export "init")
+                i32.const 16
+                i32.const 0
+                i32.const 4
+                memory.init 2
+                data.drop 2)
+            (func $only_user
+                i32.const 0
+                i32.const 0
+                i32.const 6
+                memory.init 1))
+    "#);
+    assert_eq!(data(&output), [b"active".to_vec(), b"used".to_vec()]);
+
+    let count = Parser::new(0).parse_all(&output)
+        .filter_map(|payload| match payload.unwrap() {
+            Payload::DataCountSection { count, .. } => Some(count),
+            _ => None,
+        })
+        .next();
+    assert_eq!(count, Some(2));
+}
+
+#[test]
+fn passive_element_segments_follow_table_init() {
+    let output = gc(r#"
+        (module
+            (table 2 funcref)
+            (elem funcref (ref.func $a))
+            (elem funcref (ref.func $b))
+            (func $a)
+            (func $b)
+            (func (export "init")
+                i32.const 0
+                i32.const 0
+                i32.const 1
+                table.init 0 1
+                elem.drop 1))
+    "#);
+    assert_eq!(elements(&output), [["b"]]);
+    assert_eq!(kept(&output, "func"), ["b"]);
+}
+
+#[test]
+fn active_element_segments_follow_their_table() {
+    let output = gc(r#"
+        (module
+            (table 1 funcref)
+            (table 1 funcref)
+            (elem (table 0) (i32.const 0) func $in_dead)
+            (elem (table 1) (i32.const 0) func $in_live)
+            (func $in_dead)
+            (func $in_live)
+            (func (export "call")
+                i32.const 0
+                call_indirect 1))
+    "#);
+    assert_eq!(elements(&output), [["in_live"]]);
+    assert_eq!(kept(&output, "func"), ["in_live"]);
+}
+
+#[test]
+fn imported_tables_keep_their_segments() {
+    // The host can read what the segment writes to an imported table.
+    let output = gc(r#"
+        (module
+            (import "env" "table" (table 1 funcref))
+            (elem (i32.const 0) func $placed)
+            (func $placed))
+    "#);
+    assert_eq!(elements(&output), [["placed"]]);
+    assert_eq!(kept(&output, "func"), ["placed"]);
+}
+
+#[test]
+fn segments_that_may_trap_are_kept() {
+    // Instantiating the input traps because of the out of bounds or
+    // unknown offsets, so the output has to as well. Only the segments
+    // known to fit are removed along with their memory or table.
+    let output = gc(r#"
+        (module
+            (import "env" "offset" (global i32))
+            (memory 1)
+            (memory 2)
+            (memory 3)
+            (table 1 funcref)
+            (table 2 funcref)
+            (data (memory 0) (i32.const 0) "fits")
+            (data (memory 1) (i32.const 131070) "out of bounds")
+            (data (memory 2) (global.get 0) "unknown")
+            (elem (table 0) (i32.const 0) func $fits)
+            (elem (table 1) (i32.const 1) func $out_of_bounds $out_of_bounds)
+            (func $fits)
+            (func $out_of_bounds)
+            (func (export "f")))
+    "#);
+    assert_eq!(memories(&output), [2, 3]);
+    assert_eq!(data(&output), [b"out of bounds".to_vec(), b"unknown".to_vec()]);
+    assert_eq!(tables(&output), [2]);
+    assert_eq!(elements(&output), [["out_of_bounds", "out_of_bounds"]]);
+}
+
+#[test]
+fn ref_func_declared_by_segment_on_dead_table() {
+    let output = gc(r#"
+        (module
+            (table 1 funcref)
+            (elem (table 0) (i32.const 0) func $f)
+            (func $f)
+            (func $get (export "get") (result funcref)
+                ref.func $f))
+    "#);
+    assert_eq!(elements(&output), [["f"]]);
+    assert_eq!(kept(&output, "func"), ["f", "get"]);
+}
+
+#[test]
+fn ref_func_declared_by_unused_passive_segment() {
+    let output = gc(r#"
+        (module
+            (elem func $f)
+            (func $f)
+            (func $get (export "get") (result funcref)
+                ref.func $f))
+    "#);
+    assert_eq!(elements(&output), [["f"]]);
+    assert_eq!(kept(&output, "func"), ["f", "get"]);
+}
@@ -0,0 +1,98 @@
+extern crate wasm_gc;
+extern crate wasmparser;
+extern crate wat;
+
+mod common;
+
+use wasm_gc::{Config, Error};
+use wasmparser::{Parser, Payload};
+
+const BASE64: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Returns the offset of the first instruction of each function body.
+fn body_offsets(wasm: &[u8]) -> Vec<u64> {
+    let mut offsets = Vec::new();
+    for payload in Parser::new(0).parse_all(wasm) {
+        if let Payload::CodeSectionEntry(body) = payload.unwrap() {
+            let reader = body.get_operators_reader().unwrap();
+            offsets.push(reader.original_position() as u64);
+        }
+    }
+    offsets
+}
+
+fn encode_vlq(out: &mut String, value: u64) {
+    let mut value = value << 1;
+    loop {
+        let mut digit = value & 0x1f;
+        value >>= 5;
+        if value != 0 {
+            digit |= 0x20;
+        }
+        out.push(BASE64[digit as usize] as char);
+        if value == 0 {
+            break
+        }
+    }
+}
+
+/// Returns a source map with a single line of mappings from the given
+/// columns to the given lines of `lib.rs`, both increasing.
+fn source_map(segments: &[(u64, u64)]) -> String {
+    let mut mappings = String::new();
+    let mut last = (0, 0);
+    for (i, &(column, line)) in segments.iter().enumerate() {
+        if i > 0 {
+            mappings.push(',');
+        }
+        encode_vlq(&mut mappings, column - last.0);
+        encode_vlq(&mut mappings, 0);
+        encode_vlq(&mut mappings, line - last.1);
+        encode_vlq(&mut mappings, 0);
+        last = (column, line);
+    }
+    format!(r#"{{"version":3,"sources":["lib.rs"],"names":[],"mappings":"{}"}}"#,
+            mappings)
+}
+
+#[test]
+fn mappings_follow_the_code() {
+    let input = wat::parse_str(r#"
+        (module
+            (func (export "a") (result i32)
+                i32.const 1)
+            (func $unused (result i32)
+                i32.const 2)
+            (func (export "c") (result i32)
+                i32.const 3))
+    "#).unwrap();
+    let offsets = body_offsets(&input);
+    let map = source_map(&[(offsets[0], 1), (offsets[1], 2), (offsets[2], 3)]);
+    let (output, _, map) = Config::new().source_map(&input, &map).unwrap();
+    common::validate(&output).unwrap();
+    let offsets = body_offsets(&output);
+    assert_eq!(map, source_map(&[(offsets[0], 1), (offsets[1], 3)]));
+}
+
+#[test]
+fn source_maps_are_not_reused() {
+    let input = wat::parse_str(r#"(module (func (export "a")))"#).unwrap();
+    let map = source_map(&[(body_offsets(&input)[0], 1)]);
+    let other = map.replace("lib.rs", "other.rs");
+    let mut config = Config::new();
+    let (_, _, rewritten) = config.source_map(&input, &map).unwrap();
+    assert!(rewritten.contains("lib.rs"));
+    config.gc(&input).unwrap();
+    let (_, _, rewritten) = config.source_map(&input, &other).unwrap();
+    assert!(rewritten.contains("other.rs") && !rewritten.contains("lib.rs"));
+}
+
+#[test]
+fn malformed_source_maps_are_errors() {
+    let input = wat::parse_str(r#"(module (func (export "a")))"#).unwrap();
+    match Config::new().source_map(&input, "{}") {
+        Err(Error::MalformedSourceMap(_)) => {}
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("source map without mappings was accepted"),
+    }
+}
@@ -0,0 +1,88 @@
+extern crate wasm_gc;
+extern crate wasmparser;
+extern crate wat;
+
+mod common;
+
+use common::validate;
+use wasm_gc::{Config, Error};
+
+/// A module using `i32.extend8_s` from the sign-ext proposal, with the
+/// `target_features` section `features`, if any.
+fn module(features: Option<&str>) -> Vec<u8> {
+    let section = match features {
+        Some(data) => format!("(@custom \"target_features\" \"{}\")", data),
+        None => String::new(),
+    };
+    let wat = format!(r#"
+        (module
+            {}
+            (func (export "f") (param i32) (result i32)
+                local.get 0
+                i32.extend8_s))
+    "#, section);
+    wat::parse_str(&wat).unwrap()
+}
+
+fn gc(config: &mut Config, input: &[u8]) -> Result<Vec<u8>, Error> {
+    validate(input).expect("invalid input");
+    config.gc(input)
+}
+
+#[test]
+fn undeclared_feature_is_a_warning() {
+    let input = module(Some("\\01+\\07simd128"));
+    let output = gc(&mut Config::new(), &input).unwrap();
+    validate(&output).unwrap();
+}
+
+#[test]
+fn undeclared_feature_is_rejected() {
+    let input = module(Some("\\01+\\07simd128"));
+    match gc(Config::new().reject_undeclared_features(true), &input) {
+        Err(Error::UndeclaredFeature(_)) => {}
+        other => panic!("expected an undeclared feature: {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn declared_feature_is_accepted() {
+    let input = module(Some("\\02+\\07simd128+\\08sign-ext"));
+    gc(Config::new().reject_undeclared_features(true), &input).unwrap();
+}
+
+#[test]
+fn disallowed_feature_is_not_declared() {
+    let input = module(Some("\\01-\\08sign-ext"));
+    assert!(gc(Config::new().reject_undeclared_features(true), &input).is_err());
+}
+
+#[test]
+fn modules_without_target_features_are_not_checked() {
+    gc(Config::new().reject_undeclared_features(true), &module(None)).unwrap();
+}
+
+#[test]
+fn features_of_removed_code_are_not_checked() {
+    let input = wat::parse_str(r#"
+        (module
+            (@custom "target_features" "\01+\07simd128")
+            (func $unused (param i32) (result i32)
+                local.get 0
+                i32.extend8_s)
+            (func (export "f")))
+    "#).unwrap();
+    gc(Config::new().reject_undeclared_features(true), &input).unwrap();
+}
+
+#[test]
+fn truncated_target_features_are_rejected() {
+    // Two features are announced, but only one follows.
+    let input = module(Some("\\02+\\07simd128"));
+    let output = gc(&mut Config::new(), &input).unwrap();
+    validate(&output).unwrap();
+    match gc(Config::new().reject_undeclared_features(true), &input) {
+        Err(Error::MalformedTargetFeatures(_)) => {}
+        other => panic!("expected a malformed section: {:?}", other.map(|_| ())),
+    }
+}
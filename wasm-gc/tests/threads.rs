@@ -0,0 +1,89 @@
+extern crate wasm_gc;
+extern crate wasmparser;
+extern crate wat;
+
+mod common;
+
+use common::{gc, gc_with, memories};
+use wasm_gc::Config;
+use wasmparser::{Operator, Parser, Payload};
+
+const MODULE: &str = r#"
+    (module
+        (memory (export "memory") 1 1 shared)
+        (global $tls_base (export "__tls_base") (mut i32) (i32.const 0))
+        (global $tls_size (export "__tls_size") i32 (i32.const 16))
+        (func (export "__wasm_init_tls") (param i32)
+            local.get 0
+            global.set $tls_base)
+        (func (export "main"))
+        (func (export "helper")))
+"#;
+
+fn exports(config: &mut Config) -> Vec<String> {
+    common::exports(&gc_with(config, MODULE))
+}
+
+#[test]
+fn allowlist_keeps_thread_runtime_exports() {
+    let names = exports(Config::new().export_allowlist(vec!["memory", "helper"]));
+    assert_eq!(names, ["memory", "__tls_base", "__tls_size", "__wasm_init_tls", "helper"]);
+}
+
+#[test]
+fn reject_unlisted_ignores_thread_runtime_exports() {
+    let names = exports(Config::new()
+        .export_allowlist(vec!["memory", "main", "helper"])
+        .reject_unlisted_exports(true));
+    assert_eq!(names, ["memory", "__tls_base", "__tls_size", "__wasm_init_tls", "main", "helper"]);
+}
+
+#[test]
+fn explicit_removal_wins() {
+    let names = exports(Config::new()
+        .export_allowlist(vec!["memory", "helper"])
+        .remove_export("__tls_*")
+        .blacklist("__wasm_init_tls"));
+    assert_eq!(names, ["memory", "helper"]);
+}
+
+#[test]
+fn atomic_memory_indices_are_renumbered() {
+    // The first memory is dead, so the shared one becomes memory 0.
+    let output = gc(r#"
+        (module
+            (memory 2)
+            (memory 1 1 shared)
+            (func (export "run") (result i32)
+                i32.const 0
+                i32.const 1
+                i32.atomic.rmw.add 1
+                drop
+                i32.const 0
+                i32.const 1
+                memory.atomic.notify 1
+                drop
+                i32.const 0
+                i32.const 0
+                i64.const -1
+                memory.atomic.wait32 1))
+    "#);
+    assert_eq!(memories(&output), [1]);
+
+    let mut immediates = Vec::new();
+    for payload in Parser::new(0).parse_all(&output) {
+        if let Payload::CodeSectionEntry(body) = payload.unwrap() {
+            for op in body.get_operators_reader().unwrap() {
+                match op.unwrap() {
+                    Operator::I32AtomicRmwAdd { memarg } |
+                    Operator::MemoryAtomicNotify { memarg } |
+                    Operator::MemoryAtomicWait32 { memarg } => {
+                        immediates.push(memarg.memory);
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    assert_eq!(immediates, [0, 0, 0]);
+}
@@ -0,0 +1,76 @@
+extern crate wasm_gc;
+extern crate wat;
+
+use wasm_gc::{Analysis, Config, Item};
+
+const MODULE: &str = r#"
+    (module
+        (global $counter (mut i32) (i32.const 0))
+        (func $leaf)
+        (func $mid
+            global.get $counter
+            drop
+            call $leaf)
+        (func $a (export "a")
+            call $mid)
+        (func $b (export "b")
+            call $mid
+            call $leaf)
+        (func $dead
+            call $leaf)
+        (func $_ZN3foo3bar17h0123456789abcdefE (export "foo")))
+"#;
+
+fn analyze() -> Analysis {
+    Config::new().analyze(&wat::parse_str(MODULE).unwrap()).unwrap()
+}
+
+#[test]
+fn path_starts_at_the_root() {
+    let analysis = analyze();
+    assert_eq!(analysis.why_live(Item::Function(1)),
+               Some(vec![Item::Export(0), Item::Function(2), Item::Function(1)]));
+    assert_eq!(analysis.why_live(Item::Export(0)), Some(vec![Item::Export(0)]));
+}
+
+#[test]
+fn path_is_the_shortest() {
+    // `leaf` is reachable through `a` and `mid` too, but `b` calls it
+    // directly.
+    assert_eq!(analyze().why_live(Item::Function(0)),
+               Some(vec![Item::Export(1), Item::Function(3), Item::Function(0)]));
+}
+
+#[test]
+fn dead_items_have_no_path() {
+    let analysis = analyze();
+    assert!(!analysis.is_live(Item::Function(4)));
+    assert_eq!(analysis.why_live(Item::Function(4)), None);
+}
+
+#[test]
+fn find_prefers_functions() {
+    let analysis = analyze();
+    assert_eq!(analysis.find("a"), Some(Item::Function(2)));
+    assert_eq!(analysis.find("dead"), Some(Item::Function(4)));
+    assert_eq!(analysis.find("missing"), None);
+}
+
+#[test]
+fn find_other_items() {
+    let analysis = analyze();
+    assert_eq!(analysis.find("counter"), Some(Item::Global(0)));
+    assert_eq!(analysis.why_live(Item::Global(0)),
+               Some(vec![Item::Export(0), Item::Function(2), Item::Function(1),
+                         Item::Global(0)]));
+}
+
+#[test]
+fn find_by_mangled_or_demangled_name() {
+    let analysis = analyze();
+    let foo = Some(Item::Function(5));
+    assert_eq!(analysis.find("_ZN3foo3bar17h0123456789abcdefE"), foo);
+    assert_eq!(analysis.find("foo::bar::h0123456789abcdef"), foo);
+    assert_eq!(analysis.find("foo::bar"), foo);
+    assert_eq!(analysis.find("foo"), Some(Item::Export(2)));
+}